@@ -1,279 +1,796 @@
-use chrono::Utc;
-use clap::Parser;
-use std::fs;
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use clap::{CommandFactory, Parser};
+use std::io::{IsTerminal, Write};
 use timelog::*;
 
 fn main() {
     let cli = Cli::parse();
+    // a broken config is reported by the commands that need it; color just falls back
+    let config_color = load_config().ok().and_then(|c| c.color);
+    set_color_choice(cli.color.or(config_color).unwrap_or(ColorChoice::Auto));
+    set_strict_records(cli.strict);
+    set_verbosity(cli.quiet, cli.verbose);
+    if cli.verbose > 0 {
+        log_paths();
+    }
 
     match &cli.command {
-        Commands::Start { task, project } => {
-            if state_path().exists() {
-                die("a task is already in progress; run `timelog pause` or `timelog stop`");
+        Commands::Start {
+            task,
+            project,
+            note,
+            tags,
+            at,
+            name,
+            pomodoro,
+        } => {
+            if state_present(name).unwrap_or_else(|e| die(&e.to_string())) {
+                die(&format!(
+                    "a task is already in progress{}; run `timelog pause` or `timelog stop`{}",
+                    timer_label(name),
+                    name_flag(name)
+                ));
             }
+            let project = &resolve_project(project);
+            let tags = parse_tags(tags).unwrap_or_else(|e| die_with(EXIT_USAGE, &e));
 
+            let now = match at {
+                Some(at) => parse_at(at, Utc::now()).unwrap_or_else(|e| die_with(EXIT_USAGE, &e)),
+                None => Utc::now(),
+            };
             let state = State {
-                timestamp: Utc::now(),
-                task: task.to_string(),
-                active: true,
-                project: project.clone(),
+                note: note.clone(),
+                tags,
+                ..State::started(task, project.clone(), now)
             };
-            if let Err(e) = save_state(&state) {
+            if let Err(e) = save_state(name, &state) {
                 die(&e);
             }
+            log_event(EventKind::Start, &state, now);
 
-            let project_info = match project {
-                Some(p) => format!(" in project {}", emph(p)),
-                None => String::new(),
-            };
-            info(&format!("started {}{}", emph(task), project_info));
+            info(&format!(
+                "started {}{}{}",
+                emph(task),
+                project_info(project),
+                timer_label(name)
+            ));
+            if *pomodoro {
+                if is_tty() {
+                    run_pomodoro(name, &state);
+                } else {
+                    warn("--pomodoro needs a terminal; the timer is running without reminders");
+                }
+            }
+        }
+
+        Commands::Switch { task, project } => {
+            let now = Utc::now();
+            let project = &resolve_project(project);
+
+            if state_present(DEFAULT_TIMER).unwrap_or_else(|e| die(&e.to_string())) {
+                let state = match load_state(DEFAULT_TIMER) {
+                    Ok(s) => s,
+                    Err(e) => die(&e.to_string()),
+                };
+                let day_start = load_config_or_default().day_start();
+                let records = state.to_records(now, day_start);
+                if let Err(e) = open_record_store().append_all(&records) {
+                    die(&e);
+                }
+                log_event(EventKind::Stop, &state, now);
+                for record in &records {
+                    info(&recorded_message(record));
+                }
+            }
+
+            // overwriting the state finalizes the old task and starts the new one together
+            let state = State::started(task, project.clone(), now);
+            if let Err(e) = save_state(DEFAULT_TIMER, &state) {
+                die(&e);
+            }
+            log_event(EventKind::Start, &state, now);
+
+            info(&format!("started {}{}", emph(task), project_info(project)));
+        }
+
+        Commands::Push { task, project } => {
+            let now = Utc::now();
+            let project = &resolve_project(project);
+            let next = State::started(task, project.clone(), now);
+            let previous = push_state(&next, now).unwrap_or_else(|e| die(&e));
+            if previous.active {
+                log_event(EventKind::Pause, &previous, now);
+            }
+            log_event(EventKind::Start, &next, now);
+
+            let depth = load_stack().map(|stack| stack.len()).unwrap_or_default();
+            info(&format!(
+                "pushed {} aside ({depth} on the stack); started {}{}",
+                emph(&previous.task),
+                emph(task),
+                project_info(project)
+            ));
         }
 
-        Commands::Pause => {
-            if !state_path().exists() {
-                die("no active task to pause");
+        Commands::Pop => {
+            let depth = load_stack().unwrap_or_else(|e| die(&e.to_string())).len();
+            if depth == 0 {
+                die("the task stack is empty; nothing to pop");
             }
 
-            let state = match load_state() {
+            let day_start = load_config_or_default().day_start();
+            let now = Utc::now();
+            let (replaced, resumed) = pop_state(now).unwrap_or_else(|e| die(&e));
+            let mut records = Vec::new();
+            if let Some(state) = &replaced {
+                records = state.to_records(now, day_start);
+                if let Err(e) = open_record_store().append_all(&records) {
+                    if let Err(undo) = unpop_state(Some(state), &resumed, now) {
+                        warn(&format!("couldn't put {} back: {undo}", emph(&state.task)));
+                    }
+                    die(&e);
+                }
+                log_event(EventKind::Stop, state, now);
+            }
+            log_event(EventKind::Resume, &resumed, now);
+
+            for record in &records {
+                info(&recorded_message(record));
+            }
+            info(&format!(
+                "resumed {}{} ({} left on the stack)",
+                emph(&resumed.task),
+                project_info(&resumed.project),
+                depth - 1
+            ));
+        }
+
+        Commands::Pause { name } => {
+            if !state_present(name).unwrap_or_else(|e| die(&e.to_string())) {
+                die(&format!("no active task to pause{}", timer_label(name)));
+            }
+
+            let state = match load_state(name) {
                 Ok(s) => s,
-                Err(e) => die(&e),
+                Err(e) => die(&e.to_string()),
             };
             if !state.active {
-                die("task is already paused; use `timelog resume`");
+                die(&format!(
+                    "task is already paused; use `timelog resume`{}",
+                    name_flag(name)
+                ));
             }
 
-            let elapsed = Utc::now() - state.timestamp;
-            let epoch = chrono::DateTime::<Utc>::from_timestamp(0, 0).unwrap();
-            let timestamp = epoch + elapsed;
-
-            let paused_state = State {
-                timestamp,
-                task: state.task.clone(),
-                active: false,
-                project: state.project.clone(),
-            };
-            if let Err(e) = save_state(&paused_state) {
+            let now = Utc::now();
+            let paused_state = state.paused(now);
+            if let Err(e) = save_state(name, &paused_state) {
                 die(&e);
             }
+            log_event(EventKind::Pause, &state, now);
 
             info(&format!(
-                "paused {}  (elapsed {})",
+                "paused {}  (elapsed {}){}",
                 emph(&state.task),
-                fmt_hms_ms(elapsed.num_milliseconds()),
+                fmt_hms_ms(paused_state.elapsed_ms(now)),
+                timer_label(name),
             ));
         }
 
-        Commands::Resume => {
-            if !state_path().exists() {
-                die("no paused task to resume");
+        Commands::Resume { name } => {
+            if !state_present(name).unwrap_or_else(|e| die(&e.to_string())) {
+                die(&format!("no paused task to resume{}", timer_label(name)));
             }
 
-            let state = match load_state() {
+            let state = match load_state(name) {
                 Ok(s) => s,
-                Err(e) => die(&e),
+                Err(e) => die(&e.to_string()),
             };
             if state.active {
                 die("task is already running");
             }
 
-            let epoch = chrono::DateTime::<Utc>::from_timestamp(0, 0).unwrap();
-            let elapsed = state.timestamp - epoch;
-            let timestamp = Utc::now() - elapsed;
-
-            let active_state = State {
-                timestamp,
-                task: state.task.clone(),
-                active: true,
-                project: state.project.clone(),
-            };
-            if let Err(e) = save_state(&active_state) {
+            let now = Utc::now();
+            let active_state = state.resumed(now);
+            if let Err(e) = save_state(name, &active_state) {
                 die(&e);
             }
+            log_event(EventKind::Resume, &state, now);
 
-            info(&format!("resumed {}", emph(&state.task)));
+            info(&format!(
+                "resumed {}{}",
+                emph(&state.task),
+                timer_label(name)
+            ));
         }
 
-        Commands::Stop => {
-            if !state_path().exists() {
-                die("no task to stop");
+        Commands::Stop {
+            discard,
+            continue_task,
+            yes,
+            at,
+            name,
+        } => {
+            if !state_present(name).unwrap_or_else(|e| die(&e.to_string())) {
+                die(&format!("no task to stop{}", timer_label(name)));
             }
 
-            let state = match load_state() {
+            let state = match load_state(name) {
                 Ok(s) => s,
-                Err(e) => die(&e),
+                Err(e) => die(&e.to_string()),
             };
 
-            let elapsed = if state.active {
-                Utc::now() - state.timestamp
-            } else {
-                let epoch = chrono::DateTime::<Utc>::from_timestamp(0, 0).unwrap();
-                state.timestamp - epoch
-            };
+            if *discard {
+                discard_state(name, &state);
+                return;
+            }
 
-            let record = Record {
-                task: state.task.clone(),
-                duration_ms: elapsed.num_milliseconds(),
-                date: Utc::now().naive_local().date(),
-                project: state.project.clone(),
+            // one timestamp for both the end of this session and the start of the next
+            let now = match at {
+                Some(at) => {
+                    let at = parse_at(at, Utc::now()).unwrap_or_else(|e| die_with(EXIT_USAGE, &e));
+                    if !state.active {
+                        die(&format!(
+                            "--at needs a running task; resume it first{}",
+                            timer_label(name)
+                        ));
+                    }
+                    if at < state.timestamp {
+                        die(&format!(
+                            "--at {} is before {} started at {}",
+                            at.with_timezone(&chrono::Local).format("%H:%M"),
+                            state.task,
+                            state
+                                .timestamp
+                                .with_timezone(&chrono::Local)
+                                .format("%H:%M")
+                        ));
+                    }
+                    at
+                }
+                None => Utc::now(),
             };
+            let config = load_config_or_default();
+            if let Some(warning) =
+                idle_warning(state.elapsed_ms(now), config.idle_threshold_hours())
+            {
+                warn(&warning);
+                // scripts get the warning but are never blocked on a prompt
+                let interactive = is_tty() && std::io::stdin().is_terminal();
+                if interactive && !*yes && !confirm("record it anyway?") {
+                    info(
+                        "nothing recorded; the timer is still running (`timelog stop --discard` drops it)",
+                    );
+                    return;
+                }
+            }
+            // a session crossing midnight is filed as one record per day
+            let records = state.to_records(now, config.day_start());
 
-            if let Err(e) = save_record(&record) {
+            if let Err(e) = open_record_store().append_all(&records) {
                 die(&e);
             }
+            log_event(EventKind::Stop, &state, now);
 
-            if let Err(e) = delete_state() {
+            if *continue_task {
+                let next = State::started(&state.task, state.project.clone(), now);
+                if let Err(e) = save_state(name, &next) {
+                    die(&e);
+                }
+                log_event(EventKind::Start, &next, now);
+            } else if let Err(e) = delete_state(name) {
                 die(&e);
             }
 
-            let project_info = match &record.project {
-                Some(p) => format!(" in project {}", emph(p)),
-                None => String::new(),
+            for record in &records {
+                info(&recorded_message(record));
+            }
+            if *continue_task {
+                info(&format!("continuing {}", emph(&state.task)));
+            }
+        }
+
+        Commands::Cancel { force } => {
+            let path = state_path().unwrap_or_else(|e| die(&e));
+            if *force {
+                if !path.exists() {
+                    die("no task to cancel");
+                }
+                if let Err(e) = remove_state_file() {
+                    die(&e);
+                }
+                info(&format!("removed state file {}", path.display()));
+                return;
+            }
+            let state = match load_timers() {
+                Ok(mut timers) => timers
+                    .remove(DEFAULT_TIMER)
+                    .unwrap_or_else(|| die("no task to cancel")),
+                Err(StateError::Corrupt { .. }) => {
+                    // nothing in a corrupt state file can be recorded, so just clear it
+                    if let Err(e) = remove_state_file() {
+                        die(&e);
+                    }
+                    info(&format!("removed corrupt state file {}", path.display()));
+                    return;
+                }
+                Err(e) => die(&e.to_string()),
+            };
+            discard_state(DEFAULT_TIMER, &state);
+        }
+
+        Commands::Restart => {
+            if state_present(DEFAULT_TIMER).unwrap_or_else(|e| die(&e.to_string())) {
+                die("a task is already in progress; run `timelog pause` or `timelog stop`");
+            }
+            let records = load_records_or_empty();
+            // imports can land out of order, so go by end time rather than file position
+            let Some(last) = records.iter().max_by_key(|r| r.end) else {
+                die_with(EXIT_NO_DATA, "no records to restart from");
             };
+
+            let now = Utc::now();
+            let state = State::started(&last.task, last.project.clone(), now);
+            if let Err(e) = save_state(DEFAULT_TIMER, &state) {
+                die(&e);
+            }
+            log_event(EventKind::Start, &state, now);
+
             info(&format!(
-                "recorded {}{}  {} on {}",
-                emph(&record.task),
-                project_info,
-                fmt_hms_ms(record.duration_ms),
-                record.date,
+                "restarted {}{}",
+                emph(&last.task),
+                project_info(&last.project)
             ));
         }
 
-        Commands::Report { period, project } => {
-            let records = match load_records() {
-                Ok(r) => r,
-                Err(e) => die(&e),
+        Commands::Report {
+            period,
+            from,
+            to,
+            since,
+            min_date,
+            max_date,
+            project,
+            no_project,
+            task,
+            compare,
+            exclude_projects,
+            cap_total,
+            show_gaps,
+            check_overlaps,
+            fill_gaps,
+            group_by,
+            relative_dates,
+            rate,
+            currency_total_only,
+            summary_line: summary,
+            total_only,
+            billable,
+            format,
+            task_aliases,
+            max_rows_warn,
+            projects_only,
+            tags,
+            show_notes,
+            hours_decimal,
+            chart,
+            top,
+            sort,
+            reverse,
+            highlight_tasks,
+            weekday_breakdown,
+            total_per_tag,
+            collapse_same_day_runs: collapse,
+            since_start_of_day,
+            round,
+            round_scope,
+            round_mode,
+            from_file,
+            yes,
+        } => {
+            let project = &project_filter(project, *no_project);
+            let cap_ms = cap_total
+                .as_ref()
+                .map(|c| parse_duration(c).unwrap_or_else(|e| die_with(EXIT_USAGE, &e)));
+            let rounding = round.as_ref().map(|r| {
+                let increment_ms = parse_duration(r).unwrap_or_else(|e| die_with(EXIT_USAGE, &e));
+                if increment_ms <= 0 {
+                    die_with(EXIT_USAGE, "--round must be positive");
+                }
+                Rounding {
+                    increment_ms,
+                    scope: *round_scope,
+                    mode: *round_mode,
+                }
+            });
+
+            let mut config = load_config().unwrap_or_else(|e| die(&e));
+            if let Some(time) = since_start_of_day {
+                config.workday_start = Some(parse_time_of_day(time).unwrap_or_else(|e| die(&e)));
+            }
+            let records = match from_file {
+                Some(path) => load_records_from_file(path),
+                None => load_records_or_empty(),
             };
+            let mut records = clamp_dates(records, *min_date, *max_date);
+            // runs share date and project, so collapsing before filtering is safe
+            // and keeps "consecutive" meaning consecutive in the file
+            if *collapse {
+                records = collapse_same_day_runs(records);
+            }
 
-            let today = Utc::now().date_naive();
-            let (start, end) = period_range(period.clone(), today);
+            // an explicit period or --from/--to wins over the configured default
+            let group_by: &[GroupBy] = if *projects_only {
+                &[GroupBy::Project]
+            } else {
+                group_by
+            };
+            check_group_keys(group_by).unwrap_or_else(|e| die_with(EXIT_USAGE, &e));
+            let grouped = !matches!(group_by, [GroupBy::None]);
+            match top {
+                Some(_) if !grouped => {
+                    die_with(EXIT_USAGE, "--top needs --group-by, e.g. --group-by task")
+                }
+                Some(0) => die_with(EXIT_USAGE, "--top must be at least 1"),
+                _ => {}
+            }
+            if *fill_gaps && group_by.first() != Some(&GroupBy::Day) {
+                die_with(EXIT_USAGE, "--fill-gaps needs --group-by day");
+            }
+            // amounts are priced per record, so they'd disagree with a total rounded as a whole
+            if *billable && rounding.is_some_and(|r| r.scope == RoundScope::Total) {
+                die_with(
+                    EXIT_USAGE,
+                    "--billable prices each record; use --round-scope record with it",
+                );
+            }
 
-            let mut filtered: Vec<Record> = records
-                .into_iter()
-                .filter(|x| x.date >= start && x.date <= end)
-                .filter(|x| match project {
-                    Some(p) => x.project.as_ref() == Some(p),
-                    None => true,
-                })
-                .collect();
+            let period = match (period, from, to, since) {
+                (None, None, None, None) => config.default_period.clone(),
+                _ => period.clone(),
+            };
 
-            if filtered.is_empty() {
-                warn("no records in selected period");
+            let today = config.today(Utc::now());
+            let (start, end) = match since {
+                Some(spec) => {
+                    parse_relative_range(spec, today).unwrap_or_else(|e| die_with(EXIT_USAGE, &e))
+                }
+                None => report_range(period.clone(), *from, *to, today, &config)
+                    .unwrap_or_else(|e| die(&e)),
+            };
+
+            let tags = parse_tags(tags).unwrap_or_else(|e| die_with(EXIT_USAGE, &e));
+            records.retain(|x| {
+                matches_project(x, project.as_deref())
+                    && matches_task(x, task.as_deref())
+                    && x.project
+                        .as_ref()
+                        .is_none_or(|p| !exclude_projects.contains(p))
+                    && has_all_tags(x, &tags)
+            });
+            let in_range = |start: NaiveDate, end: NaiveDate| -> Vec<Record> {
+                records
+                    .iter()
+                    .filter(|x| x.date >= start && x.date <= end)
+                    .cloned()
+                    .collect()
+            };
+
+            if *compare {
+                let Some((current, previous)) = period
+                    .clone()
+                    .and_then(|p| p.predecessor().map(|prev| (p, prev)))
+                else {
+                    die_with(
+                        EXIT_USAGE,
+                        "--compare needs a period with a previous one: this-week, this-month or ytd",
+                    );
+                };
+                let (prev_start, prev_end) = period_range_for(previous.clone(), today, &config);
+                let rows = compare_projects(&in_range(start, end), &in_range(prev_start, prev_end));
+                if rows.is_empty() {
+                    die_with(EXIT_NO_DATA, "no records in either period");
+                }
+                print_comparison(current, previous, &rows, *hours_decimal);
                 return;
             }
 
-            // sort by date, then task
-            filtered.sort_by_key(|r| (r.date, r.task.clone()));
+            let mut filtered = in_range(start, end);
+            if *check_overlaps {
+                for overlap in find_overlaps(&filtered) {
+                    warn(&overlap.to_string());
+                }
+            }
+            if let Some(rounding) = &rounding {
+                rounding.round_records(&mut filtered, group_by);
+            }
 
-            print_report(period.clone(), start, end, &filtered, project);
-        }
+            if *currency_total_only {
+                let rate = rate.unwrap_or_else(|| {
+                    die_with(EXIT_USAGE, "--currency-total-only requires --rate")
+                });
+                let total_ms: i64 = match &rounding {
+                    Some(rounding) => rounding.total_ms(&filtered, group_by),
+                    None => filtered.iter().map(|r| r.duration_ms).sum(),
+                };
+                outln!("{:.2}", billable_amount(total_ms, rate));
+                return;
+            }
 
-        Commands::Status => {
-            if !state_path().exists() {
-                die("no task to provide status");
+            if *summary {
+                outln!(
+                    "{}",
+                    summary_line(period.clone(), &filtered, *hours_decimal)
+                );
+                return;
             }
 
-            let state = match load_state() {
-                Ok(s) => s,
-                Err(e) => die(&e),
-            };
+            if *total_only {
+                if filtered.is_empty() {
+                    die_with(EXIT_NO_DATA, "no records in selected period");
+                }
+                let total_ms: i64 = match &rounding {
+                    Some(rounding) => rounding.total_ms(&filtered, group_by),
+                    None => filtered.iter().map(|r| r.duration_ms).sum(),
+                };
+                let opts = ReportOptions {
+                    project_filter: project.clone(),
+                    billing: billable.then(|| Billing {
+                        default_rate: rate.or(config.billing.default_rate),
+                        ..config.billing.clone()
+                    }),
+                    hours_decimal: *hours_decimal,
+                    ..ReportOptions::default()
+                };
+                print_report_total(period.clone(), start, end, &filtered, total_ms, &opts);
+                return;
+            }
 
-            let epoch = chrono::DateTime::<Utc>::from_timestamp(0, 0).unwrap();
+            if *task_aliases {
+                apply_task_aliases(&mut filtered, &config.task_aliases);
+            }
 
-            // If active, elapsed = now - started_at; if paused, elapsed = stored
-            let (elapsed_ms, since_ts, _status_str) = if state.active {
-                let e = (Utc::now() - state.timestamp).num_milliseconds();
-                (clamp_nonneg(e), state.timestamp, "active")
-            } else {
-                let e = (state.timestamp - epoch).num_milliseconds();
-                (clamp_nonneg(e), state.timestamp, "paused")
-            };
+            // by date, then task (ties broken by project and duration), unless --sort says otherwise
+            sort_records_by(&mut filtered, *sort, *reverse);
 
-            // Pretty, concise status lines
-            let project_info = match &state.project {
-                Some(p) => format!(" in project {}", emph(p)),
-                None => String::new(),
-            };
+            // --format wins over `[report] format` in the config, which wins over table
+            let format = format
+                .clone()
+                .or_else(|| config.report.format.clone())
+                .unwrap_or(ReportFormat::Table);
+            match format {
+                ReportFormat::Table => {}
+                ReportFormat::Json => {
+                    // an empty period is still a valid document for scripts
+                    let mut report = ReportJson::new(start, end, &filtered);
+                    if grouped {
+                        let mut groups = group_records_nested(&filtered, group_by);
+                        if *fill_gaps {
+                            fill_day_gaps(&mut groups, start, end.min(today));
+                        }
+                        if let Some(rounding) = &rounding {
+                            rounding.round_groups(&mut groups);
+                        }
+                        if let Some(n) = top {
+                            groups = top_groups(groups, *n);
+                        }
+                        report.groups = Some(groups);
+                    }
+                    if let Some(rounding) = &rounding {
+                        report.total_ms = rounding.total_ms(&filtered, group_by);
+                    }
+                    match serde_json::to_string_pretty(&report) {
+                        Ok(json) => outln!("{json}"),
+                        Err(e) => die(&format!("Failed to serialize report: {e}")),
+                    }
+                    return;
+                }
+                ReportFormat::Csv => {
+                    if let Err(e) = write_records_csv(&filtered, Output) {
+                        die(&e);
+                    }
+                    return;
+                }
+            }
 
-            if state.active {
-                // e.g., "active 00:42:10.123 since 2025-08-08T17:20:11Z  —  task: compile in project myproject"
-                info(&format!(
-                    "{}  {}  since {}  —  task: {}{}",
-                    emph("active"),
-                    fmt_hms_ms(elapsed_ms),
-                    fmt_ts(since_ts),
-                    emph(&state.task),
-                    project_info,
-                ));
+            if filtered.is_empty() {
+                die_with(EXIT_NO_DATA, "no records in selected period");
             } else {
-                // When paused, `since_ts` is the pause timestamp encoded in state.timestamp
-                info(&format!(
-                    "{}  accumulated {}  —  task: {}{}",
-                    emph("paused"),
-                    fmt_hms_ms(elapsed_ms),
-                    emph(&state.task),
-                    project_info,
+                let opts = ReportOptions {
+                    project_filter: project.clone(),
+                    cap_ms,
+                    relative_to: relative_dates.then_some(today),
+                    show_notes: *show_notes,
+                    highlight_tasks: highlight_tasks.clone(),
+                    chart: chart.then(Chart::for_output),
+                    billing: billable.then(|| Billing {
+                        default_rate: rate.or(config.billing.default_rate),
+                        ..config.billing.clone()
+                    }),
+                    rounding,
+                    hours_decimal: *hours_decimal,
+                };
+                let mut groups = group_records_nested(&filtered, group_by);
+                if *fill_gaps {
+                    fill_day_gaps(&mut groups, start, end.min(today));
+                }
+                if let Some(rounding) = &rounding {
+                    rounding.round_groups(&mut groups);
+                }
+                if let Some(n) = top {
+                    groups = top_groups(groups, *n);
+                }
+                let limit = max_rows_warn
+                    .or(config.max_rows_warn)
+                    .unwrap_or(DEFAULT_MAX_ROWS_WARN);
+                let interactive = is_tty() && std::io::stdin().is_terminal();
+                if needs_row_confirmation(groups.len(), limit, interactive, *yes)
+                    && !confirm(&format!("this will print {} rows, continue?", groups.len()))
+                {
+                    return;
+                }
+
+                if grouped {
+                    print_group_report(
+                        period.clone(),
+                        start,
+                        end,
+                        &groups,
+                        &group_header(group_by),
+                        &opts,
+                    );
+                } else {
+                    print_report(period.clone(), start, end, &filtered, &opts);
+                }
+
+                if *weekday_breakdown {
+                    print_weekday_breakdown(&filtered);
+                }
+                if *total_per_tag {
+                    print_tag_totals(&filtered);
+                }
+            }
+
+            if *show_gaps {
+                for date in untracked_weekdays(&filtered, start, end, &config.weekend_days()) {
+                    warn(&format!(
+                        "no time tracked on {} {date}",
+                        weekday_short(date.weekday())
+                    ));
+                }
+            }
+        }
+
+        Commands::Status { watch, json, name } => {
+            if *watch {
+                watch_status(name.as_deref().unwrap_or(DEFAULT_TIMER));
+                return;
+            }
+            if *json {
+                // prompts poll this, so no task (or an unreadable one) is not an error
+                let state = read_state_if_present(name.as_deref().unwrap_or(DEFAULT_TIMER));
+                outln!("{}", status_json(state.as_ref(), Utc::now()));
+                return;
+            }
+
+            let mut timers = load_timers().unwrap_or_else(|e| die(&e.to_string()));
+            if let Some(name) = name {
+                timers.retain(|n, _| n == name);
+            }
+            // suspended tasks belong to the default timer
+            let stack = match name.as_deref() {
+                None | Some(DEFAULT_TIMER) => load_stack().unwrap_or_else(|e| die(&e.to_string())),
+                Some(_) => Vec::new(),
+            };
+            if timers.is_empty() && stack.is_empty() {
+                die(&format!(
+                    "no task to provide status{}",
+                    name.as_deref().map(timer_label).unwrap_or_default()
                 ));
             }
+
+            let now = Utc::now();
+            let threshold = load_config_or_default().idle_threshold_hours();
+            for (name, state) in &timers {
+                // If active, elapsed = now - started_at; if paused, elapsed = stored
+                let elapsed_ms = clamp_nonneg(state.elapsed_ms(now));
+                if let Some(warning) = idle_warning(elapsed_ms, threshold) {
+                    warn(&warning);
+                }
+                print_status(state, elapsed_ms, &timer_label(name));
+            }
+            if !stack.is_empty() {
+                let tasks: Vec<&str> = stack.iter().rev().map(|s| s.task.as_str()).collect();
+                outln!(
+                    "{}  {} suspended, next up first: {}",
+                    emph("stack"),
+                    stack.len(),
+                    tasks.join(", ")
+                );
+            }
         }
 
         Commands::Amend {
             date,
             task,
+            last,
+            scale_duration,
+            project,
             new_task,
             new_duration,
             new_project,
+            new_note,
             dry_run,
         } => {
-            // Parse the date string
-            let parsed_date = match date.parse() {
-                Ok(d) => d,
-                Err(_) => die(&format!(
-                    "Invalid date format '{date}'. Use YYYY-MM-DD format"
-                )),
-            };
-
-            let mut records = match load_records() {
+            // the store is rewritten, so a skipped row would be lost, and so would
+            // a record appended between loading and rewriting
+            let store = open_record_store();
+            let _lock = store.lock().unwrap_or_else(|e| die(&e));
+            let mut records = match store.load_strict() {
                 Ok(r) => r,
                 Err(e) => die(&e),
             };
 
-            // Find matching records
-            let matching_indices: Vec<usize> = records
-                .iter()
-                .enumerate()
-                .filter(|(_, record)| record.date == parsed_date && record.task.contains(task))
-                .map(|(i, _)| i)
-                .collect();
-
-            if matching_indices.is_empty() {
-                die(&format!(
-                    "No records found matching date {parsed_date} and task pattern '{task}'"
-                ));
+            if let Some(factor) = *scale_duration {
+                let Some(date) = date else {
+                    die_with(EXIT_USAGE, "--scale-duration needs --date");
+                };
+                scale_durations(
+                    store.as_ref(),
+                    &mut records,
+                    date,
+                    task,
+                    project,
+                    factor,
+                    *dry_run,
+                );
+                return;
             }
 
-            if matching_indices.len() > 1 {
-                warn(&format!(
-                    "Found {} matching records. Please be more specific with your task pattern:",
-                    matching_indices.len()
-                ));
-                for &i in &matching_indices {
-                    let record = &records[i];
-                    let project_info = match &record.project {
-                        Some(p) => format!(" (project: {p})"),
-                        None => String::new(),
-                    };
-                    println!(
-                        "  {} - {} - {}{}",
-                        record.date,
-                        record.task,
-                        fmt_hms_ms(record.duration_ms),
-                        project_info
+            let record_index = if *last {
+                match last_record_index(&records) {
+                    Some(i) => i,
+                    None => die_with(EXIT_NO_DATA, "No records to amend"),
+                }
+            } else {
+                let (Some(date), Some(task)) = (date, task) else {
+                    die_with(
+                        EXIT_USAGE,
+                        "Specify the record with --date and --task, or use --last",
                     );
+                };
+
+                // Parse the date string
+                let parsed_date: chrono::NaiveDate = match date.parse() {
+                    Ok(d) => d,
+                    Err(_) => die(&format!(
+                        "Invalid date format '{date}'. Use YYYY-MM-DD format"
+                    )),
+                };
+
+                // Find matching records
+                let matching_indices = find_matching_records(&records, parsed_date, task);
+
+                if matching_indices.is_empty() {
+                    die(&format!(
+                        "No records found matching date {parsed_date} and task pattern '{task}'"
+                    ));
+                }
+
+                if matching_indices.len() > 1 {
+                    warn(&format!(
+                        "Found {} matching records. Please be more specific with your task pattern:",
+                        matching_indices.len()
+                    ));
+                    for &i in &matching_indices {
+                        let record = &records[i];
+                        outln!("  {}", record_line(record));
+                    }
+                    die("Use a more specific task pattern to match exactly one record");
                 }
-                die("Use a more specific task pattern to match exactly one record");
-            }
 
-            let record_index = matching_indices[0];
+                matching_indices[0]
+            };
+
             let original_record = records[record_index].clone();
             let mut amended_record = original_record.clone();
 
@@ -288,11 +805,12 @@ fn main() {
                 ));
             }
 
-            if let Some(duration_min) = new_duration {
-                if *duration_min <= 0 {
-                    die("Duration must be positive");
+            if let Some(duration) = new_duration {
+                let duration_ms = parse_duration(duration).unwrap_or_else(|e| die(&e));
+                if duration_ms <= 0 {
+                    die_with(EXIT_USAGE, "Duration must be positive");
                 }
-                amended_record.duration_ms = duration_min * 60 * 1000; // Convert minutes to milliseconds
+                amended_record.set_duration_ms(duration_ms);
                 changes.push(format!(
                     "duration: {} → {}",
                     fmt_hms_ms(original_record.duration_ms),
@@ -312,27 +830,32 @@ fn main() {
                 amended_record.project = new_proj;
             }
 
+            if let Some(note) = new_note {
+                let new_note = (!note.is_empty()).then(|| note.clone());
+                let old_note_str = original_record.note.as_deref().unwrap_or("(none)");
+                let new_note_str = new_note.as_deref().unwrap_or("(none)");
+                changes.push(format!("note: {old_note_str} → {new_note_str}"));
+                amended_record.note = new_note;
+            }
+
             if changes.is_empty() {
-                die("No changes specified. Use --new-task, --new-duration, or --new-project");
+                die(
+                    "No changes specified. Use --new-task, --new-duration, --new-project, or --new-note",
+                );
             }
 
             // Show what will be changed
-            println!("Found record to amend:");
-            let project_info = match &original_record.project {
-                Some(p) => format!(" (project: {p})"),
-                None => String::new(),
-            };
-            println!(
-                "  {} - {} - {}{}",
-                original_record.date,
-                original_record.task,
-                fmt_hms_ms(original_record.duration_ms),
-                project_info
-            );
+            outln!("Found record to amend:");
+            outln!("  {}", record_line(&original_record));
 
-            println!("\nChanges to apply:");
+            outln!("\nChanges to apply:");
             for change in &changes {
-                println!("  {change}");
+                outln!("  {change}");
+            }
+
+            outln!();
+            for line in record_diff(&original_record, &amended_record) {
+                outln!("  {line}");
             }
 
             if *dry_run {
@@ -343,7 +866,7 @@ fn main() {
             // Apply the amendment
             records[record_index] = amended_record.clone();
 
-            if let Err(e) = save_records(&records) {
+            if let Err(e) = store.rewrite(&records) {
                 die(&e);
             }
 
@@ -354,106 +877,918 @@ fn main() {
         }
 
         Commands::Upload {
-            plugin,
+            plugins,
+            all_plugins,
             period,
             dry_run,
             list_plugins,
+            timeout,
+            project,
+            no_project,
         } => {
             if *list_plugins {
-                let plugins = discover_plugins();
+                let plugins = discover_plugins().unwrap_or_else(|e| die(&e));
                 if plugins.is_empty() {
                     info("No plugins found");
                     info(&format!(
                         "Place plugin scripts in: {}",
-                        plugin_dir().display()
+                        plugin_dir().unwrap_or_else(|e| die(&e)).display()
                     ));
                     info("Plugin scripts should be named 'timelog-<name>' and be executable");
                 } else {
-                    info("Available plugins:");
+                    outln!("Available plugins:");
                     for p in plugins {
-                        println!("  • {p}");
+                        outln!("  • {p}");
                     }
                 }
                 return;
             }
 
             // Load records for the specified period
-            let records = match load_records() {
-                Ok(r) => r,
-                Err(e) => die(&e),
-            };
+            let config = load_config().unwrap_or_else(|e| die(&e));
+            let records = load_records_or_empty();
 
             let period = period.as_ref().unwrap(); // Safe because of required_unless_present
-            let today = Utc::now().date_naive();
-            let (start, end) = period_range(period.clone(), today);
-            let filtered: Vec<Record> = records
+            let today = config.today(Utc::now());
+            let (start, end) = period_range_for(period.clone(), today, &config);
+            let project = project_filter(project, *no_project);
+            let mut filtered: Vec<Record> = records
                 .into_iter()
                 .filter(|x| x.date >= start && x.date <= end)
+                .filter(|x| matches_project(x, project.as_deref()))
                 .collect();
+            // every plugin gets the records in the same stable order
+            sort_plugin_records(&mut filtered);
 
             if filtered.is_empty() {
-                warn("no records in selected period");
-                return;
+                die_with(EXIT_NO_DATA, "no records in selected period");
             }
 
-            let plugin_name = if let Some(p) = plugin {
-                p.clone()
+            let plugin_names = if *all_plugins {
+                let installed = discover_plugins().unwrap_or_else(|e| die(&e));
+                if installed.is_empty() {
+                    die("No plugins available. Use --list-plugins to see setup instructions.");
+                }
+                installed
+            } else if !plugins.is_empty() {
+                plugins.clone()
             } else {
-                let plugins = discover_plugins();
-                if plugins.is_empty() {
+                let installed = discover_plugins().unwrap_or_else(|e| die(&e));
+                if installed.is_empty() {
                     die("No plugins available. Use --list-plugins to see setup instructions.");
-                } else if plugins.len() == 1 {
-                    plugins[0].clone()
+                } else if installed.len() == 1 {
+                    installed
                 } else {
-                    die("Multiple plugins available, specify one with --plugin <name>");
+                    die_with(
+                        EXIT_USAGE,
+                        "Multiple plugins available, specify one with --plugin <name>",
+                    );
                 }
             };
 
-            // Load plugin config
-            let config_path = plugin_dir().join(format!("timelog-{plugin_name}.json"));
-            let config = if config_path.exists() {
-                let config_str = fs::read_to_string(config_path)
-                    .unwrap_or_else(|_| die("Failed to read plugin config"));
-                serde_json::from_str(&config_str)
-                    .unwrap_or_else(|_| die("Invalid plugin config JSON"))
-            } else {
-                serde_json::Value::Object(serde_json::Map::new())
+            let period_str = format!("{period:?}").to_lowercase();
+            // a plugin with a broken config fails on its own; the rest still run
+            let mut jobs = Vec::new();
+            let mut outcomes = Vec::new();
+            for name in &plugin_names {
+                let negotiated = load_plugin_config(name).and_then(|plugin_config| {
+                    let version = plugin_protocol_version(&plugin_config)
+                        .map_err(|e| format!("Refusing to run plugin '{name}': {e}"))?;
+                    Ok((plugin_config, version))
+                });
+                match negotiated {
+                    Ok((plugin_config, protocol_version)) => jobs.push(PluginJob {
+                        name: name.clone(),
+                        timeout: plugin_timeout(*timeout, &plugin_config),
+                        input: PluginInput {
+                            protocol_version,
+                            records: filtered.clone(),
+                            period: period_str.clone(),
+                            config: plugin_config,
+                        },
+                    }),
+                    Err(e) => outcomes.push((name.clone(), Err(e))),
+                }
+            }
+
+            for job in &jobs {
+                info(&format!("Executing plugin: {}", emph(&job.name)));
+            }
+            if *dry_run {
+                info("(dry run mode)");
+            }
+
+            let results = execute_plugins(&jobs, *dry_run);
+            outcomes.extend(jobs.into_iter().map(|job| job.name).zip(results));
+            outcomes.sort_by_key(|(name, _)| plugin_names.iter().position(|n| n == name));
+
+            let succeeded: Vec<bool> = outcomes
+                .iter()
+                .map(|(name, result)| {
+                    if plugin_names.len() > 1 {
+                        info(&format!("{}:", emph(name)));
+                    }
+                    print_plugin_result(name, result, filtered.len(), cli.strict)
+                })
+                .collect();
+            if plugin_names.len() > 1 {
+                outln!("\nUpload summary:");
+                for ((name, result), ok) in outcomes.iter().zip(&succeeded) {
+                    let status = match result {
+                        Ok(output) if *ok => match output.uploaded_count {
+                            Some(count) => format!("ok, {}", count_of(count, "record")),
+                            None => "ok".to_string(),
+                        },
+                        _ => "FAILED".to_string(),
+                    };
+                    outln!("  {name}: {status}");
+                }
+            }
+            let failed = succeeded.iter().filter(|ok| !**ok).count();
+            if failed > 0 {
+                die_with(
+                    EXIT_PLUGIN,
+                    &format!("{} failed", count_of(failed, "plugin")),
+                );
+            }
+        }
+
+        Commands::Add {
+            task,
+            date,
+            start,
+            end,
+            duration,
+            project,
+        } => {
+            let parse_time = |t: &Option<String>| {
+                t.as_deref()
+                    .map(|t| parse_time_of_day(t).unwrap_or_else(|e| die(&e)))
             };
+            let duration_ms = duration
+                .as_deref()
+                .map(|d| parse_duration(d).unwrap_or_else(|e| die(&e)));
+            let (start, end) = (parse_time(start), parse_time(end));
+            let duration_ms =
+                entry_duration_ms(start, end, duration_ms).unwrap_or_else(|e| die(&e));
+            let date = date.unwrap_or_else(|| load_config_or_default().today(Utc::now()));
 
-            let period_str = format!("{period:?}").to_lowercase();
-            let input = PluginInput {
-                records: filtered,
-                period: period_str,
-                config,
+            // with neither time given the record falls back to a synthesized span
+            let project = resolve_project(project);
+            let length = chrono::Duration::milliseconds(duration_ms);
+            let record = match (start, end) {
+                (Some(start), _) => {
+                    let start = local_datetime(date, start);
+                    Record::spanning(task, start, start + length, project)
+                }
+                (None, Some(end)) => {
+                    let end = local_datetime(date, end);
+                    Record::spanning(task, end - length, end, project)
+                }
+                (None, None) => Record::new(task, duration_ms, date, project),
+            };
+            let record = Record { date, ..record };
+            if let Err(e) = open_record_store().append(&record) {
+                die(&e);
+            }
+            info(&recorded_message(&record));
+        }
+
+        Commands::Delete {
+            date,
+            task,
+            index,
+            dry_run,
+        } => {
+            // the store is rewritten, so a skipped row would be lost, and so would
+            // a record appended between loading and rewriting
+            let store = open_record_store();
+            let _lock = store.lock().unwrap_or_else(|e| die(&e));
+            let mut records = match store.load_strict() {
+                Ok(r) => r,
+                Err(e) => die(&e),
+            };
+
+            let matching_indices = find_matching_records(&records, *date, task);
+            if matching_indices.is_empty() {
+                die(&format!(
+                    "No records found matching date {date} and task pattern '{task}'"
+                ));
+            }
+
+            let record_index = match index {
+                Some(n) if *n >= 1 && *n <= matching_indices.len() => matching_indices[n - 1],
+                Some(n) => die(&format!(
+                    "--index {n} is out of range; {} records match",
+                    matching_indices.len()
+                )),
+                None if matching_indices.len() == 1 => matching_indices[0],
+                None => {
+                    warn(&format!(
+                        "Found {} matching records. Choose one with --index:",
+                        matching_indices.len()
+                    ));
+                    for (n, &i) in matching_indices.iter().enumerate() {
+                        let record = &records[i];
+                        outln!("  {}. {}", n + 1, record_line(record));
+                    }
+                    die("Use --index to delete exactly one record");
+                }
             };
 
-            info(&format!("Executing plugin: {}", emph(&plugin_name)));
+            let record = records[record_index].clone();
+            outln!("Found record to delete:");
+            outln!("  {}", record_line(&record));
+
             if *dry_run {
-                info("(dry run mode)");
+                info("Dry run mode - no changes were made");
+                return;
             }
 
-            match execute_plugin(&plugin_name, &input, *dry_run) {
-                Ok(output) => {
-                    if output.success {
-                        info(&output.message.to_string());
-                        if let Some(count) = output.uploaded_count {
-                            info(&format!("Processed {count} records"));
-                        }
-                        if !output.errors.is_empty() {
-                            warn("Some warnings occurred:");
-                            for error in output.errors {
-                                warn(&format!("  {error}"));
-                            }
-                        }
-                    } else {
-                        warn(&format!("Plugin failed: {}", output.message));
-                        for error in output.errors {
-                            warn(&format!("  {error}"));
-                        }
+            records.remove(record_index);
+            if let Err(e) = store.rewrite(&records) {
+                die(&e);
+            }
+
+            info(&format!(
+                "Deleted {} on {} ({})",
+                emph(&record.task),
+                record.date,
+                fmt_hms_ms(record.duration_ms)
+            ));
+        }
+
+        Commands::Export {
+            period,
+            format,
+            split_by,
+            out_dir,
+            from_file,
+            exclude_projects,
+            task_aliases,
+        } => {
+            if *format != ExportFormat::Csv && split_by.is_some() {
+                die_with(EXIT_USAGE, "--split-by only supports --format csv");
+            }
+            let config = load_config().unwrap_or_else(|e| die(&e));
+            let (start, end) = period_range_for(period.clone(), config.today(Utc::now()), &config);
+            let records = match from_file {
+                Some(path) => load_records_from_file(path),
+                None => load_records_or_empty(),
+            };
+            let mut filtered: Vec<Record> = records
+                .into_iter()
+                .filter(|x| x.date >= start && x.date <= end)
+                .filter(|x| {
+                    x.project
+                        .as_ref()
+                        .is_none_or(|p| !exclude_projects.contains(p))
+                })
+                .collect();
+            if *task_aliases {
+                apply_task_aliases(&mut filtered, &config.task_aliases);
+            }
+            sort_records(&mut filtered);
+
+            match (split_by, out_dir) {
+                (Some(SplitBy::Project), Some(dir)) => {
+                    let buckets = split_by_project(&filtered);
+                    for path in export_split(&buckets, dir).unwrap_or_else(|e| die(&e)) {
+                        info(&format!("wrote {}", path.display()));
+                    }
+                }
+                _ => {
+                    let out = Output;
+                    let written = match format {
+                        ExportFormat::Csv => write_records_csv(&filtered, out),
+                        ExportFormat::Ics => write_records_ics(&filtered, Utc::now(), out),
+                        ExportFormat::IsoDuration => write_records_iso_duration_csv(&filtered, out),
+                    };
+                    if let Err(e) = written {
+                        die(&e);
                     }
                 }
-                Err(e) => die(&format!("Plugin execution failed: {e}")),
             }
         }
+
+        Commands::Completions { shell } => {
+            let mut cmd = Cli::command();
+            let name = cmd.get_name().to_string();
+            clap_complete::generate(*shell, &mut cmd, name, &mut Output);
+            if let Some(script) = task_completion_script(*shell) {
+                out!("{script}");
+            }
+        }
+
+        Commands::CompleteTasks => {
+            // Completion must stay quiet, so a broken config or store just completes nothing.
+            let tasks = match load_config().and_then(|c| storage_location(&c)) {
+                Ok((Storage::Csv, path)) => task_names_from_path(&path),
+                Ok((storage, path)) => open_store(storage, path)
+                    .and_then(|store| store.load())
+                    .map(|records| {
+                        let mut tasks: Vec<String> = records.into_iter().map(|r| r.task).collect();
+                        tasks.sort();
+                        tasks.dedup();
+                        tasks
+                    })
+                    .unwrap_or_default(),
+                Err(_) => Vec::new(),
+            };
+            for task in tasks {
+                outln!("{task}");
+            }
+        }
+
+        Commands::Migrate { from } => {
+            let config = load_config().unwrap_or_else(|e| die(&e));
+            let (storage, db_path) = storage_location(&config).unwrap_or_else(|e| die(&e));
+            if storage != Storage::Sqlite {
+                die(
+                    "migrate copies records into SQLite; set `storage = \"sqlite\"` in the config or point TIMELOG_RECORD_PATH at a .db file first",
+                );
+            }
+            let from = match from {
+                Some(from) => from.clone(),
+                None => record_path().unwrap_or_else(|e| die(&e)),
+            };
+            if from == db_path {
+                die(
+                    "TIMELOG_RECORD_PATH is the database itself; pass --from with the CSV record file to copy",
+                );
+            }
+            let (records, skipped) = read_records_from_path(&from).unwrap_or_else(|e| die(&e));
+            if let Some(row) = skipped.first() {
+                die(&format!(
+                    "line {} of {}: {}; fix or remove it first",
+                    row.line,
+                    from.display(),
+                    row.error
+                ));
+            }
+            if records.iter().any(|r| !r.extra.is_empty()) {
+                die(&format!(
+                    "{} has columns beyond the ones timelog knows, which the database can't keep; remove them first",
+                    from.display()
+                ));
+            }
+            let store = open_store(storage, db_path.clone()).unwrap_or_else(|e| die(&e));
+            if store.exists() && !store.load().unwrap_or_else(|e| die(&e)).is_empty() {
+                die(&format!(
+                    "{} already holds records; refusing to copy over them",
+                    db_path.display()
+                ));
+            }
+            store.append_all(&records).unwrap_or_else(|e| die(&e));
+            info(&format!(
+                "copied {} records from {} to {}",
+                records.len(),
+                from.display(),
+                db_path.display()
+            ));
+        }
+
+        Commands::Import { file, dry_run } => {
+            let (records, skipped) = read_import_file(file).unwrap_or_else(|e| die(&e));
+            for row in &skipped {
+                warn(&format!("skipping line {}: {}", row.line, row.error));
+            }
+            if !*dry_run
+                && !records.is_empty()
+                && let Err(e) = open_record_store().append_all(&records)
+            {
+                die(&e);
+            }
+
+            let verb = if *dry_run { "would import" } else { "imported" };
+            info(&format!(
+                "{verb} {} records, skipped {}",
+                records.len(),
+                skipped.len()
+            ));
+        }
+
+        Commands::Events { period, json } => {
+            if !events_path().unwrap_or_else(|e| die(&e)).exists() {
+                die(
+                    "no event log found; set `event_log = true` in config.toml to record transitions",
+                );
+            }
+
+            let config = load_config().unwrap_or_else(|e| die(&e));
+            let mut events = load_events().unwrap_or_else(|e| die(&e));
+            if let Some(period) = period {
+                let today = config.today(Utc::now());
+                let (start, end) = period_range_for(period.clone(), today, &config);
+                let day_start = config.day_start();
+                events.retain(|e| e.date(day_start) >= start && e.date(day_start) <= end);
+            }
+
+            if *json {
+                match serde_json::to_string_pretty(&events) {
+                    Ok(json) => outln!("{json}"),
+                    Err(e) => die(&format!("Failed to serialize events: {e}")),
+                }
+                return;
+            }
+
+            if events.is_empty() {
+                die_with(EXIT_NO_DATA, "no events found");
+            }
+            for event in &events {
+                outln!(
+                    "{}  {:<8}{}{}",
+                    fmt_ts(event.timestamp),
+                    event.kind.as_str(),
+                    emph(&event.task),
+                    project_info(&event.project),
+                );
+            }
+        }
+
+        Commands::Projects { period, json } => {
+            let mut records = load_records_or_empty();
+            if let Some(period) = period {
+                let config = load_config().unwrap_or_else(|e| die(&e));
+                let (start, end) =
+                    period_range_for(period.clone(), config.today(Utc::now()), &config);
+                records.retain(|r| r.date >= start && r.date <= end);
+            }
+            if *json {
+                let projects = project_summaries(&records);
+                match serde_json::to_string_pretty(&projects) {
+                    Ok(json) => outln!("{json}"),
+                    Err(e) => die(&format!("Failed to serialize projects: {e}")),
+                }
+                return;
+            }
+            let projects = project_table_rows(&records);
+            if projects.is_empty() {
+                die_with(EXIT_NO_DATA, "no projects found");
+            }
+
+            let name_w = projects
+                .iter()
+                .map(|p| p.name.len())
+                .max()
+                .unwrap_or(0)
+                .max("PROJECT".len());
+            outln!(
+                "{:<name_w$}  {:>7}  {:>10}  {:<10}",
+                "PROJECT",
+                "RECORDS",
+                "DURATION",
+                "LAST USED"
+            );
+            for p in &projects {
+                outln!(
+                    "{:<name_w$}  {:>7}  {:>10}  {:<10}",
+                    p.name,
+                    p.count,
+                    fmt_duration(p.total_ms),
+                    p.last_used
+                );
+            }
+        }
+
+        Commands::Stats {
+            period,
+            hours_decimal,
+            from_file,
+        } => {
+            let config = load_config().unwrap_or_else(|e| die(&e));
+            let today = config.today(Utc::now());
+            let (start, end) = period_range_for(period.clone(), today, &config);
+            let records = match from_file {
+                Some(path) => load_records_from_file(path),
+                None => load_records_or_empty(),
+            };
+            let in_period: Vec<Record> = records
+                .iter()
+                .filter(|r| r.date >= start && r.date <= end)
+                .cloned()
+                .collect();
+            if in_period.is_empty() {
+                die_with(EXIT_NO_DATA, "no records in selected period");
+            }
+            print_stats(
+                period.clone(),
+                start,
+                end,
+                &period_stats(&in_period, &records, today),
+                *hours_decimal,
+            );
+        }
+        Commands::Tasks { project, json } => {
+            let mut records = load_records_or_empty();
+            if let Some(project) = project {
+                records.retain(|r| r.project.as_ref() == Some(project));
+            }
+            let tasks = task_names(&records);
+            if *json {
+                match serde_json::to_string_pretty(&tasks) {
+                    Ok(json) => outln!("{json}"),
+                    Err(e) => die(&format!("Failed to serialize tasks: {e}")),
+                }
+                return;
+            }
+            if tasks.is_empty() {
+                die_with(EXIT_NO_DATA, "no tasks found");
+            }
+            for task in &tasks {
+                outln!("{task}");
+            }
+        }
+
+        Commands::Doctor => {
+            let checks = doctor_checks();
+            for check in &checks {
+                outln!("{check}");
+            }
+            let failed = checks
+                .iter()
+                .filter(|c| c.status == CheckStatus::Fail)
+                .count();
+            if failed > 0 {
+                die(&format!("{failed} check(s) failed"));
+            }
+        }
+
+        Commands::Config { action } => match action {
+            ConfigAction::Validate => {
+                let config_file = config_path().unwrap_or_else(|e| die(&e));
+                let plugins = plugin_dir().unwrap_or_else(|e| die(&e));
+                let problems = validate_config_files(&config_file, &plugins);
+                if problems.is_empty() {
+                    info(&format!("config OK ({})", config_file.display()));
+                    return;
+                }
+                for problem in &problems {
+                    outln!("  {problem}");
+                }
+                die(&format!("{} config problem(s) found", problems.len()));
+            }
+        },
+
+        Commands::Plugins { action } => match action {
+            PluginAction::Install { path, name, force } => {
+                match install_plugin(path, name.as_deref(), *force) {
+                    Ok(target) => info(&format!("installed {}", target.display())),
+                    Err(e) => die(&e),
+                }
+            }
+            PluginAction::Remove { name } => match remove_plugin(name) {
+                Ok(target) => info(&format!("removed {}", target.display())),
+                Err(e) => die(&e),
+            },
+        },
+    }
+}
+
+/// Append a transition to the event log when `event_log` is enabled in the config.
+fn log_event(kind: EventKind, state: &State, now: DateTime<Utc>) {
+    if !load_config_or_default().event_log {
+        return;
+    }
+    if let Err(e) = save_event(&Event::new(kind, state, now)) {
+        warn(&format!("event not logged: {e}"));
+    }
+}
+
+/// `--project` if given, else `default_project` from the config.
+fn resolve_project(project: &Option<String>) -> Option<String> {
+    if project.is_some() {
+        return project.clone();
+    }
+    load_config_or_default().default_project
+}
+
+/// The config, or the defaults with a warning when it's invalid; for the timer
+/// commands, which shouldn't stop tracking time over a mistake in it.
+fn load_config_or_default() -> Config {
+    load_config().unwrap_or_else(|e| {
+        warn_invalid_config(&e);
+        Config::default()
+    })
+}
+
+/// The record store selected by the record path and the config.
+fn open_record_store() -> Box<dyn RecordStore> {
+    record_store().unwrap_or_else(|e| die(&e))
+}
+
+/// Report one plugin's outcome for `sent` records; false when it failed.
+fn print_plugin_result(
+    name: &str,
+    result: &Result<PluginOutput, String>,
+    sent: usize,
+    strict: bool,
+) -> bool {
+    if let Ok(output) = result {
+        for problem in output.inconsistencies(sent) {
+            warn(&format!("plugin '{name}' {problem}"));
+        }
+    }
+    match result {
+        Ok(output) if output.succeeded(strict) => {
+            info(&output.message.to_string());
+            if let Some(count) = output.uploaded_count {
+                info(&format!("Processed {count} records"));
+            }
+            if !output.errors.is_empty() {
+                warn("Some warnings occurred:");
+                for error in &output.errors {
+                    warn(&format!("  {error}"));
+                }
+            }
+            true
+        }
+        Ok(output) if output.success => {
+            warn(&format!(
+                "Plugin reported errors, which --strict counts as a failure: {}",
+                output.message
+            ));
+            for error in &output.errors {
+                warn(&format!("  {error}"));
+            }
+            false
+        }
+        Ok(output) => {
+            warn(&format!("Plugin failed: {}", output.message));
+            for error in &output.errors {
+                warn(&format!("  {error}"));
+            }
+            false
+        }
+        Err(e) => {
+            warn(&format!("Plugin execution failed: {e}"));
+            false
+        }
+    }
+}
+
+/// `--verbose`: where this run reads and writes.
+fn log_paths() {
+    let shown = |path: Result<std::path::PathBuf, String>| match path {
+        Ok(path) => path.display().to_string(),
+        Err(e) => format!("unresolved ({e})"),
+    };
+    debug(1, &format!("config: {}", shown(config_path())));
+    debug(1, &format!("state: {}", shown(state_path())));
+    match load_config().and_then(|c| storage_location(&c)) {
+        Ok((storage, path)) => debug(1, &format!("records ({storage:?}): {}", path.display())),
+        Err(_) => debug(1, &format!("records: {}", shown(record_path()))),
+    }
+    debug(1, &format!("events: {}", shown(events_path())));
+    debug(1, &format!("plugins: {}", shown(plugin_dir())));
+}
+
+/// All records, or none when nothing has been recorded yet.
+fn load_records_or_empty() -> Vec<Record> {
+    let store = open_record_store();
+    if !store.exists() {
+        return Vec::new();
+    }
+    store.load().unwrap_or_else(|e| die(&e))
+}
+
+/// `amend --scale-duration`: multiply the duration of the matching records on `date`.
+fn scale_durations(
+    store: &dyn RecordStore,
+    records: &mut [Record],
+    date: &str,
+    task: &Option<String>,
+    project: &Option<String>,
+    factor: f64,
+    dry_run: bool,
+) {
+    if !(factor.is_finite() && factor > 0.0) {
+        die_with(
+            EXIT_USAGE,
+            &format!("Scale factor must be positive, got {factor}"),
+        );
+    }
+    let parsed_date: chrono::NaiveDate = match date.parse() {
+        Ok(d) => d,
+        Err(_) => die(&format!(
+            "Invalid date format '{date}'. Use YYYY-MM-DD format"
+        )),
+    };
+
+    let indices = find_records_to_scale(records, parsed_date, task.as_deref(), project.as_deref());
+    if indices.is_empty() {
+        die_with(
+            EXIT_NO_DATA,
+            &format!("No records found on {parsed_date} to scale"),
+        );
+    }
+
+    let mut before_ms = 0;
+    let mut after_ms = 0;
+    outln!("Records to scale by {factor}:");
+    for &i in &indices {
+        let record = &mut records[i];
+        let scaled_ms = scale_duration_ms(record.duration_ms, factor);
+        if scaled_ms <= 0 {
+            die(&format!(
+                "Scaling would leave no time on {}",
+                record_line(record)
+            ));
+        }
+        outln!(
+            "  {} - {}: {} → {}",
+            record.date,
+            record.task,
+            fmt_hms_ms(record.duration_ms),
+            fmt_hms_ms(scaled_ms)
+        );
+        before_ms += record.duration_ms;
+        after_ms += scaled_ms;
+        record.set_duration_ms(scaled_ms);
+    }
+    outln!(
+        "\nTotal: {} → {}",
+        fmt_hms_ms(before_ms),
+        fmt_hms_ms(after_ms)
+    );
+
+    if dry_run {
+        info("Dry run mode - no changes were made");
+        return;
+    }
+
+    if let Err(e) = store.rewrite(records) {
+        die(&e);
+    }
+    info(&format!(
+        "Successfully scaled {} on {parsed_date}",
+        count_of(indices.len(), "record")
+    ));
+}
+
+/// Records from a `--from-file` CSV, which unlike the record file must exist.
+fn load_records_from_file(path: &std::path::Path) -> Vec<Record> {
+    if !path.exists() {
+        die(&format!("{}: no such file", path.display()));
+    }
+    load_records_from_path(path).unwrap_or_else(|e| die(&e))
+}
+
+/// Drop the running or paused task without writing a record.
+fn discard_state(name: &str, state: &State) {
+    let now = Utc::now();
+    if let Err(e) = delete_state(name) {
+        die(&e);
+    }
+    log_event(EventKind::Discard, state, now);
+    info(&format!(
+        "discarded {} (elapsed {}){}",
+        emph(&state.task),
+        fmt_hms_ms(clamp_nonneg(state.elapsed_ms(now))),
+        timer_label(name),
+    ));
+}
+
+/// ` (timer <name>)` for messages about a named timer; empty for the default one.
+fn timer_label(name: &str) -> String {
+    if name == DEFAULT_TIMER {
+        String::new()
+    } else {
+        format!(" (timer {name})")
+    }
+}
+
+/// The `--name` flag that targets timer `name` again, for hints; empty for the default.
+fn name_flag(name: &str) -> String {
+    if name == DEFAULT_TIMER {
+        String::new()
+    } else {
+        format!(" --name {name}")
+    }
+}
+
+/// One `status` line for a timer; `label` names a timer other than the default.
+fn print_status(state: &State, elapsed_ms: i64, label: &str) {
+    let project_info = project_info(&state.project);
+
+    // the status line is what was asked for, so --quiet keeps it
+    if state.active {
+        // e.g., "active 00:42:10.123 since 2025-08-08T17:20:11Z  —  task: compile in project myproject"
+        outln!(
+            "{}  {}  since {}  —  task: {}{}{label}",
+            emph("active"),
+            fmt_hms_ms(elapsed_ms),
+            fmt_ts(state.timestamp),
+            emph(&state.task),
+            project_info,
+        );
+    } else {
+        // When paused, the accumulated time is encoded in state.timestamp
+        outln!(
+            "{}  accumulated {}  —  task: {}{}{label}",
+            emph("paused"),
+            fmt_hms_ms(elapsed_ms),
+            emph(&state.task),
+            project_info,
+        );
+    }
+}
+
+static INTERRUPTED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Catch Ctrl-C in `INTERRUPTED` instead of exiting, so a foreground loop can
+/// finish on its own terms.
+#[cfg(unix)]
+fn catch_sigint() {
+    extern "C" fn on_sigint(_: libc::c_int) {
+        INTERRUPTED.store(true, std::sync::atomic::Ordering::SeqCst);
+    }
+    // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            on_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+}
+
+// elsewhere Ctrl-C ends the process outright, which still leaves the timer running
+#[cfg(not(unix))]
+fn catch_sigint() {}
+
+/// Announce pomodoro work and break intervals for timer `name` until Ctrl-C,
+/// or until the timer is stopped or replaced by another command.
+fn run_pomodoro(name: &str, state: &State) {
+    let config = load_config_or_default();
+    let (work, rest) = config.pomodoro_minutes();
+    let bell = if config.pomodoro_bell { "\x07" } else { "" };
+    catch_sigint();
+    // the notices are what --pomodoro asked for, so --quiet keeps them
+    outln!("pomodoro: {work}m of work, then a {rest}m break; Ctrl-C leaves the timer running");
+
+    let mut phase = pomodoro_phase(state.elapsed_ms(Utc::now()), work, rest);
+    while !INTERRUPTED.load(std::sync::atomic::Ordering::SeqCst) {
+        std::thread::sleep(std::time::Duration::from_millis(250));
+        let Some(current) = read_state_if_present(name) else {
+            outln!("pomodoro: the timer has stopped");
+            return;
+        };
+        if current.task != state.task {
+            outln!("pomodoro: the timer now tracks another task");
+            return;
+        }
+        let next = pomodoro_phase(current.elapsed_ms(Utc::now()), work, rest);
+        if next == phase {
+            continue;
+        }
+        match next {
+            PomodoroPhase::Break { completed } => {
+                outln!("{bell}pomodoro {completed} done: time for a {rest}m break")
+            }
+            PomodoroPhase::Work { .. } => {
+                outln!("{bell}break over: back to {}", emph(&state.task))
+            }
+        }
+        phase = next;
+    }
+    outln!(
+        "\npomodoro reminders off; {} is still running{}",
+        emph(&state.task),
+        timer_label(name)
+    );
+}
+
+/// Print a JSON status line for timer `name` now and again whenever it changes,
+/// until interrupted.
+fn watch_status(name: &str) {
+    use notify::Watcher;
+
+    // state files are replaced by rename, so watch the directory rather than the file
+    let path = state_path().unwrap_or_else(|e| die(&e));
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => std::path::PathBuf::from("."),
+    };
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)
+        .unwrap_or_else(|e| die(&format!("Failed to watch state file: {e}")));
+    if let Err(e) = watcher.watch(&dir, notify::RecursiveMode::NonRecursive) {
+        die(&format!("Failed to watch {}: {e}", dir.display()));
+    }
+
+    let mut last: Option<Option<State>> = None;
+    loop {
+        // other files in the directory change too; only print when the state itself did
+        let current = read_state_if_present(name);
+        if last.as_ref() != Some(&current) {
+            outln!("{}", status_json(current.as_ref(), Utc::now()));
+            if Output.flush().is_err() {
+                return;
+            }
+            last = Some(current);
+        }
+        if rx.recv().is_err() {
+            return;
+        }
     }
 }
@@ -0,0 +1,155 @@
+//! The opt-in SQLite record store, built with the `sqlite` cargo feature.
+//!
+//! Columns mirror the record file: tags are one `;`-joined column and timestamps
+//! RFC 3339 text, so `timelog migrate` is a straight copy in either direction.
+
+use crate::{FileLock, Record, RecordStore, lock_file, split_tags};
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use rusqlite::{Connection, params};
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub struct SqliteStore {
+    path: PathBuf,
+}
+
+impl SqliteStore {
+    pub fn open(path: PathBuf) -> Result<SqliteStore, String> {
+        Ok(SqliteStore { path })
+    }
+
+    fn connect(&self) -> Result<Connection, String> {
+        if let Some(dir) = self.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {e}", dir.display()))?;
+        }
+        let conn = Connection::open(&self.path)
+            .map_err(|e| format!("Failed to open {}: {e}", self.path.display()))?;
+        conn.busy_timeout(Duration::from_secs(5))
+            .map_err(|e| format!("Failed to configure {}: {e}", self.path.display()))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS records (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                task TEXT NOT NULL,
+                duration_ms INTEGER NOT NULL,
+                date TEXT NOT NULL,
+                project TEXT,
+                note TEXT,
+                tags TEXT NOT NULL DEFAULT '',
+                start TEXT NOT NULL,
+                end TEXT NOT NULL
+            )",
+        )
+        .map_err(|e| format!("Failed to create records table: {e}"))?;
+        Ok(conn)
+    }
+
+    fn insert(conn: &Connection, records: &[Record]) -> Result<(), String> {
+        let mut stmt = conn
+            .prepare(
+                "INSERT INTO records (task, duration_ms, date, project, note, tags, start, end)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            )
+            .map_err(|e| format!("Failed to write record: {e}"))?;
+        for r in records {
+            stmt.execute(params![
+                r.task,
+                r.duration_ms,
+                r.date.to_string(),
+                r.project,
+                r.note,
+                r.tags.join(";"),
+                r.start.to_rfc3339_opts(SecondsFormat::Millis, true),
+                r.end.to_rfc3339_opts(SecondsFormat::Millis, true),
+            ])
+            .map_err(|e| format!("Failed to write record: {e}"))?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_row(row: &rusqlite::Row) -> rusqlite::Result<Result<Record, String>> {
+    let id: i64 = row.get(0)?;
+    let date: String = row.get(3)?;
+    let tags: String = row.get(6)?;
+    let start: String = row.get(7)?;
+    let end: String = row.get(8)?;
+    let timestamp = |c: &str| {
+        DateTime::parse_from_rfc3339(c)
+            .map(|dt| dt.with_timezone(&Utc))
+            .map_err(|_| format!("record {id}: invalid timestamp '{c}'"))
+    };
+    let parsed = (|| {
+        Ok(Record {
+            task: row.get(1).map_err(|e| e.to_string())?,
+            duration_ms: row.get(2).map_err(|e| e.to_string())?,
+            date: NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                .map_err(|_| format!("record {id}: invalid date '{date}'"))?,
+            project: row.get(4).map_err(|e| e.to_string())?,
+            note: row.get(5).map_err(|e| e.to_string())?,
+            tags: split_tags(&tags),
+            start: timestamp(&start)?,
+            end: timestamp(&end)?,
+            extra: Vec::new(),
+        })
+    })();
+    Ok(parsed)
+}
+
+impl RecordStore for SqliteStore {
+    fn load(&self) -> Result<Vec<Record>, String> {
+        if !self.exists() {
+            return Ok(Vec::new());
+        }
+        let conn = self.connect()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, task, duration_ms, date, project, note, tags, start, end
+                 FROM records ORDER BY id",
+            )
+            .map_err(|e| format!("Failed to read {}: {e}", self.path.display()))?;
+        let rows = stmt
+            .query_map([], parse_row)
+            .map_err(|e| format!("Failed to read {}: {e}", self.path.display()))?;
+        let mut records = Vec::new();
+        for row in rows {
+            let row = row.map_err(|e| format!("Failed to read {}: {e}", self.path.display()))?;
+            records.push(row.map_err(|e| format!("{}: {e}", self.path.display()))?);
+        }
+        Ok(records)
+    }
+
+    fn append_all(&self, records: &[Record]) -> Result<(), String> {
+        let _lock = lock_file(&self.path)?;
+        let mut conn = self.connect()?;
+        let tx = conn
+            .transaction()
+            .map_err(|e| format!("Failed to write record: {e}"))?;
+        SqliteStore::insert(&tx, records)?;
+        tx.commit()
+            .map_err(|e| format!("Failed to write record: {e}"))
+    }
+
+    fn rewrite(&self, records: &[Record]) -> Result<(), String> {
+        let _lock = lock_file(&self.path)?;
+        let mut conn = self.connect()?;
+        let tx = conn
+            .transaction()
+            .map_err(|e| format!("Failed to write records: {e}"))?;
+        tx.execute("DELETE FROM records", [])
+            .map_err(|e| format!("Failed to write records: {e}"))?;
+        SqliteStore::insert(&tx, records)?;
+        tx.commit()
+            .map_err(|e| format!("Failed to write records: {e}"))
+    }
+
+    // the same sibling lock file as the CSV store, so a load and rewrite under it
+    // can't drop a record another process appends in between
+    fn lock(&self) -> Result<Option<FileLock>, String> {
+        lock_file(&self.path).map(Some)
+    }
+
+    fn exists(&self) -> bool {
+        self.path.exists()
+    }
+}
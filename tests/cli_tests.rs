@@ -7,6 +7,14 @@ use std::thread;
 use std::time::Duration;
 use tempfile::TempDir;
 
+/// The start,end CSV columns of a record loaded without stored timestamps.
+fn synthesized_span_columns(date: chrono::NaiveDate, duration_ms: i64) -> String {
+    let (start, end) = timelog::synthesized_span(date, duration_ms);
+    let column =
+        |t: chrono::DateTime<chrono::Utc>| t.to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+    format!("{},{}", column(start), column(end))
+}
+
 /// Helper to set up a clean test environment with temporary directories
 fn setup_cli_test_env() -> TempDir {
     let temp_dir = TempDir::new().expect("Failed to create temp dir");
@@ -17,6 +25,8 @@ fn setup_cli_test_env() -> TempDir {
         env::set_var("TIMELOG_RECORD_PATH", format!("{temp_path}/records.csv"));
         env::set_var("TIMELOG_STATE_PATH", format!("{temp_path}/state.json"));
         env::set_var("TIMELOG_PLUGIN_PATH", format!("{temp_path}/plugins"));
+        env::set_var("TIMELOG_CONFIG_PATH", format!("{temp_path}/config.toml"));
+        env::set_var("TIMELOG_EVENTS_PATH", format!("{temp_path}/events.jsonl"));
     }
 
     // Create plugins directory
@@ -31,6 +41,8 @@ fn cleanup_cli_test_env() {
         env::remove_var("TIMELOG_RECORD_PATH");
         env::remove_var("TIMELOG_STATE_PATH");
         env::remove_var("TIMELOG_PLUGIN_PATH");
+        env::remove_var("TIMELOG_CONFIG_PATH");
+        env::remove_var("TIMELOG_EVENTS_PATH");
     }
 }
 
@@ -181,6 +193,66 @@ fn test_stop_workflow() {
     cleanup_cli_test_env();
 }
 
+#[test]
+#[serial]
+fn test_stop_records_project() {
+    let temp_dir = setup_cli_test_env();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["start", "client work", "--project", "acme"])
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("stop")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("acme"));
+
+    let contents = fs::read_to_string(temp_dir.path().join("records.csv")).unwrap();
+    let row: Vec<&str> = contents.lines().nth(1).unwrap().split(',').collect();
+    assert_eq!(row[0], "client work");
+    assert_eq!(row[3], "acme");
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_timer_commands_survive_an_invalid_config() {
+    let temp_dir = setup_cli_test_env();
+    fs::write(
+        temp_dir.path().join("config.toml"),
+        "fiscal_year_start_month = 13\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["start", "config typo"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("using the default settings"));
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("status")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("config typo"));
+
+    // warned about once, however many places read the config
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    let output = cmd.arg("stop").assert().success().get_output().clone();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stderr.matches("fiscal_year_start_month").count(),
+        1,
+        "{stderr}"
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains("recorded"));
+
+    cleanup_cli_test_env();
+}
+
 #[test]
 #[serial]
 fn test_report_no_records() {
@@ -189,8 +261,8 @@ fn test_report_no_records() {
     let mut cmd = Command::cargo_bin("timelog").unwrap();
     cmd.args(["report", "today"])
         .assert()
-        .failure()
-        .stderr(predicate::str::contains("no records found"));
+        .code(3)
+        .stderr(predicate::str::contains("no records in selected period"));
 
     cleanup_cli_test_env();
 }
@@ -395,16 +467,19 @@ fn test_different_period_types() {
         "last-week",
         "this-month",
         "last-month",
+        "this-quarter",
+        "last-quarter",
         "ytd",
         "last-year",
+        "fiscal-ytd",
     ];
 
     for period in periods {
         let mut cmd = Command::cargo_bin("timelog").unwrap();
         cmd.args(["report", period])
             .assert()
-            .failure() // Will fail due to no records, but should parse the period correctly
-            .stderr(predicate::str::contains("no records found"));
+            .code(3) // no records, but the period still has to parse
+            .stderr(predicate::str::contains("no records in selected period"));
     }
 
     cleanup_cli_test_env();
@@ -769,3 +844,2860 @@ fn test_amend_multiple_changes() {
 
     cleanup_cli_test_env();
 }
+
+#[test]
+#[serial]
+fn test_report_cap_total_flags_overage() {
+    let temp_dir = setup_cli_test_env();
+
+    // 100h + 70h logged today, against a 160h contractual cap
+    let today = chrono::Utc::now().date_naive();
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        format!(
+            "task,duration_ms,date,project\nbuild,360000000,{today},acme\nreview,252000000,{today},acme\n"
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today", "--cap-total", "160h"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("170h00m"))
+        .stdout(predicate::str::contains("CAPPED"))
+        .stdout(predicate::str::contains("160h00m"))
+        .stdout(predicate::str::contains("exceeded by 10h00m"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_amend_last_record_duration() {
+    let temp_dir = setup_cli_test_env();
+
+    let record_file = temp_dir.path().join("records.csv");
+    fs::write(
+        &record_file,
+        "task,duration_ms,date,project\nolder,600000,2024-01-15,\nlatest,600000,2024-01-16,\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["amend", "--last", "--new-duration", "45m"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("latest"))
+        .stdout(predicate::str::contains("00:45:00.000"));
+
+    let contents = fs::read_to_string(&record_file).unwrap();
+    assert!(contents.contains("latest,2700000,2024-01-16"));
+    assert!(contents.contains("older,600000,2024-01-15"));
+
+    // --last cannot be combined with explicit targeting
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["amend", "--last", "--task", "older", "--new-duration", "5"])
+        .assert()
+        .failure();
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_config_validate() {
+    let temp_dir = setup_cli_test_env();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["config", "validate"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("config OK"));
+
+    fs::write(
+        temp_dir.path().join("config.toml"),
+        "fiscal_year_start_month = 0\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["config", "validate"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("fiscal_year_start_month"))
+        .stderr(predicate::str::contains("1 config problem(s) found"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_stop_discard_writes_no_record() {
+    let temp_dir = setup_cli_test_env();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["start", "accidental task"]).assert().success();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["stop", "--discard"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("discarded accidental task"));
+
+    // Nothing recorded and no task left running
+    assert!(!temp_dir.path().join("records.csv").exists());
+    assert!(!temp_dir.path().join("state.json").exists());
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_custom_date_range() {
+    let temp_dir = setup_cli_test_env();
+
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        "task,duration_ms,date,project\n\
+         before,3600000,2024-03-03,\n\
+         inside,3600000,2024-03-10,\n\
+         after,3600000,2024-03-18,\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "--from", "2024-03-04", "--to", "2024-03-17"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Custom report (2024-03-04..2024-03-17)",
+        ))
+        .stdout(predicate::str::contains("inside"))
+        .stdout(predicate::str::contains("before").not())
+        .stdout(predicate::str::contains("after").not());
+
+    // A named period and a custom range are mutually exclusive
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args([
+        "report",
+        "today",
+        "--from",
+        "2024-03-04",
+        "--to",
+        "2024-03-17",
+    ])
+    .assert()
+    .failure();
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_currency_total_only() {
+    let temp_dir = setup_cli_test_env();
+
+    let today = chrono::Utc::now().date_naive();
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        format!(
+            "task,duration_ms,date,project\n\
+             design,3600000,{today},acme\n\
+             build,5400000,{today},acme\n\
+             other,3600000,{today},beta\n"
+        ),
+    )
+    .unwrap();
+
+    // 2.5h of acme at 120/h
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args([
+        "report",
+        "today",
+        "--project",
+        "acme",
+        "--rate",
+        "120",
+        "--currency-total-only",
+    ])
+    .assert()
+    .success()
+    .stdout(predicate::str::diff("300.00\n"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_json_format() {
+    let temp_dir = setup_cli_test_env();
+
+    let today = chrono::Utc::now().date_naive();
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        format!(
+            "task,duration_ms,date,project\n\
+             design,3600000,{today},acme\n\
+             build,1800000,{today},\n"
+        ),
+    )
+    .unwrap();
+
+    let output = Command::cargo_bin("timelog")
+        .unwrap()
+        .args(["report", "today", "--format", "json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(!stdout.contains('\x1b'));
+    let report: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(report["total_ms"], 5400000);
+    assert_eq!(report["records"].as_array().unwrap().len(), 2);
+    assert_eq!(report["records"][0]["task"], "build");
+    assert!(report["records"][0]["project"].is_null());
+
+    // An empty period is an empty array, not a warning
+    let output = Command::cargo_bin("timelog")
+        .unwrap()
+        .args(["report", "last-year", "--format", "json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["records"], serde_json::json!([]));
+    assert_eq!(report["total_ms"], 0);
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_csv_format() {
+    let temp_dir = setup_cli_test_env();
+
+    let today = chrono::Utc::now().date_naive();
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        format!("task,duration_ms,date,project\ndesign,3600000,{today},acme\n"),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today", "--format", "csv"])
+        .assert()
+        .success()
+        .stdout(predicate::str::diff(format!(
+            "task,duration_ms,date,project,note,tags,start,end\ndesign,3600000,{today},acme,,,{}\n",
+            synthesized_span_columns(today, 3_600_000)
+        )));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_task_aliases() {
+    let temp_dir = setup_cli_test_env();
+
+    let today = chrono::Utc::now().date_naive();
+    let record_file = temp_dir.path().join("records.csv");
+    fs::write(
+        &record_file,
+        format!("task,duration_ms,date,project\nt123,3600000,{today},\n"),
+    )
+    .unwrap();
+    fs::write(
+        temp_dir.path().join("config.toml"),
+        "[task_aliases]\nt123 = \"Implement login\"\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today", "--task-aliases"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Implement login"))
+        .stdout(predicate::str::contains("t123").not());
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["export", "today", "--task-aliases"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Implement login,3600000"))
+        .stdout(predicate::str::contains("t123").not());
+
+    // The record file still holds the code
+    assert!(fs::read_to_string(&record_file).unwrap().contains("t123"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_delete_record() {
+    let temp_dir = setup_cli_test_env();
+
+    let record_file = temp_dir.path().join("records.csv");
+    fs::write(
+        &record_file,
+        "task,duration_ms,date,project\n\
+         keep,600000,2024-01-15,\n\
+         mistake,900000,2024-01-15,acme\n",
+    )
+    .unwrap();
+
+    // Dry run leaves the file alone
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args([
+        "delete",
+        "--date",
+        "2024-01-15",
+        "--task",
+        "mist",
+        "--dry-run",
+    ])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains(
+        "Dry run mode - no changes were made",
+    ));
+    assert!(
+        fs::read_to_string(&record_file)
+            .unwrap()
+            .contains("mistake")
+    );
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["delete", "--date", "2024-01-15", "--task", "mist"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("00:15:00.000"));
+
+    let contents = fs::read_to_string(&record_file).unwrap();
+    assert!(!contents.contains("mistake"));
+    assert!(contents.contains("keep,600000,2024-01-15"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_delete_ambiguous_requires_index() {
+    let temp_dir = setup_cli_test_env();
+
+    let record_file = temp_dir.path().join("records.csv");
+    fs::write(
+        &record_file,
+        "task,duration_ms,date,project\n\
+         review a,600000,2024-01-15,\n\
+         review b,900000,2024-01-15,\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["delete", "--date", "2024-01-15", "--task", "review"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("1. 2024-01-15 - review a"))
+        .stdout(predicate::str::contains("2. 2024-01-15 - review b"))
+        .stderr(predicate::str::contains("--index"));
+    assert_eq!(fs::read_to_string(&record_file).unwrap().lines().count(), 3);
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args([
+        "delete",
+        "--date",
+        "2024-01-15",
+        "--task",
+        "review",
+        "--index",
+        "2",
+    ])
+    .assert()
+    .success();
+
+    let contents = fs::read_to_string(&record_file).unwrap();
+    assert!(contents.contains("review a"));
+    assert!(!contents.contains("review b"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_stop_continue_restarts_same_task() {
+    let temp_dir = setup_cli_test_env();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["start", "long task", "--project", "acme"])
+        .assert()
+        .success();
+
+    thread::sleep(Duration::from_millis(50));
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["stop", "--continue"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("recorded long task"))
+        .stdout(predicate::str::contains("continuing long task"));
+
+    let records = timelog::load_records_from_path(&temp_dir.path().join("records.csv")).unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].task, "long task");
+    assert_eq!(records[0].project.as_deref(), Some("acme"));
+
+    let mut timers =
+        timelog::parse_state_file(&fs::read_to_string(temp_dir.path().join("state.json")).unwrap())
+            .unwrap();
+    let state = timers.remove(timelog::DEFAULT_TIMER).unwrap();
+    assert!(state.active);
+    assert_eq!(state.task, "long task");
+    assert_eq!(state.project.as_deref(), Some("acme"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_switch_records_and_starts() {
+    let temp_dir = setup_cli_test_env();
+
+    // With nothing running, switch behaves like start
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["switch", "first task"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("started first task"))
+        .stdout(predicate::str::contains("recorded").not());
+
+    thread::sleep(Duration::from_millis(50));
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["switch", "second task", "--project", "acme"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("recorded first task"))
+        .stdout(predicate::str::contains(
+            "started second task in project acme",
+        ));
+
+    let records = timelog::load_records_from_path(&temp_dir.path().join("records.csv")).unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].task, "first task");
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("status")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("second task"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_max_rows_warn_skipped_when_piped() {
+    let temp_dir = setup_cli_test_env();
+
+    let today = chrono::Utc::now().date_naive();
+    let mut csv = String::from("task,duration_ms,date,project\n");
+    for i in 0..5 {
+        csv.push_str(&format!("task {i},60000,{today},\n"));
+    }
+    fs::write(temp_dir.path().join("records.csv"), csv).unwrap();
+
+    // Output isn't a terminal, so no prompt even though we're over the limit
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today", "--max-rows-warn", "2"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("continue?").not())
+        .stdout(predicate::str::contains("task 4"))
+        .stdout(predicate::str::contains("TOTAL"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_events_json_session() {
+    let temp_dir = setup_cli_test_env();
+    fs::write(temp_dir.path().join("config.toml"), "event_log = true\n").unwrap();
+
+    for args in [
+        vec!["start", "session task", "--project", "p"],
+        vec!["pause"],
+        vec!["resume"],
+        vec!["stop"],
+    ] {
+        let mut cmd = Command::cargo_bin("timelog").unwrap();
+        cmd.args(&args).assert().success();
+    }
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    let output = cmd.args(["events", "today", "--json"]).output().unwrap();
+    assert!(output.status.success());
+
+    let events: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let kinds: Vec<&str> = events
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["kind"].as_str().unwrap())
+        .collect();
+    assert_eq!(kinds, ["start", "pause", "resume", "stop"]);
+    assert_eq!(events[0]["task"], "session task");
+    assert_eq!(events[0]["project"], "p");
+    assert!(events[0]["timestamp"].is_string());
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_events_without_event_log() {
+    let _temp_dir = setup_cli_test_env();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["events", "--json"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("event_log = true"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_color_flag() {
+    let _temp_dir = setup_cli_test_env();
+
+    // piped output is plain by default
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["start", "plain task"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\x1b[").not());
+
+    // an explicit --color always wins over both the pipe and NO_COLOR
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.env("NO_COLOR", "1")
+        .args(["--color", "always", "status"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\x1b[1mplain task\x1b[0m"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_collapse_same_day_runs() {
+    let temp_dir = setup_cli_test_env();
+
+    let today = chrono::Utc::now().date_naive();
+    let record_file = temp_dir.path().join("records.csv");
+    let contents = format!(
+        "task,duration_ms,date,project\ndesign,1800000,{today},acme\ndesign,900000,{today},acme\n"
+    );
+    fs::write(&record_file, &contents).unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args([
+        "report",
+        "today",
+        "--format",
+        "csv",
+        "--collapse-same-day-runs",
+    ])
+    .assert()
+    .success()
+    .stdout(predicate::str::diff(format!(
+        "task,duration_ms,date,project,note,tags,start,end\ndesign,2700000,{today},acme,,,{}\n",
+        synthesized_span_columns(today, 2_700_000)
+    )));
+
+    // display only: the record file keeps both rows
+    assert_eq!(fs::read_to_string(&record_file).unwrap(), contents);
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_group_by_task() {
+    let temp_dir = setup_cli_test_env();
+
+    let today = chrono::Utc::now().date_naive();
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        format!(
+            "task,duration_ms,date,project\ndesign,1800000,{today},acme\nreview,600000,{today},\ndesign,900000,{today},beta\n"
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today", "--group-by", "task"])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"design\s+2\s+00h45m").unwrap())
+        .stdout(predicate::str::is_match(r"review\s+1\s+00h10m").unwrap())
+        .stdout(predicate::str::is_match(r"TOTAL\s+3\s+00h55m").unwrap());
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_group_by_project_task() {
+    let temp_dir = setup_cli_test_env();
+
+    let today = chrono::Utc::now().date_naive();
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        format!(
+            "task,duration_ms,date,project\n\
+             design,3600000,{today},acme\n\
+             review,1200000,{today},acme\n\
+             design,600000,{today},acme\n\
+             build,2700000,{today},beta\n\
+             review,1800000,{today},beta\n"
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    let output = cmd
+        .args(["report", "today", "--group-by", "project,task"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let rows: Vec<&str> = stdout
+        .lines()
+        .skip_while(|l| !l.starts_with("PROJECT / TASK"))
+        .map(str::trim_end)
+        .collect();
+    let pattern = [
+        r"^PROJECT / TASK\s+RECORDS\s+DURATION$",
+        r"^-+",
+        r"^acme$",
+        r"^  design\s+2\s+01h10m$",
+        r"^  review\s+1\s+00h20m$",
+        r"^  subtotal\s+3\s+01h30m$",
+        r"^beta$",
+        r"^  build\s+1\s+00h45m$",
+        r"^  review\s+1\s+00h30m$",
+        r"^  subtotal\s+2\s+01h15m$",
+        r"^-+",
+        r"^TOTAL\s+5\s+02h45m$",
+    ];
+    assert_eq!(rows.len(), pattern.len(), "{stdout}");
+    for (row, pattern) in rows.iter().zip(pattern) {
+        assert!(
+            predicate::str::is_match(pattern).unwrap().eval(row),
+            "{row:?} !~ {pattern}"
+        );
+    }
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_config_defaults_and_cli_overrides() {
+    let temp_dir = setup_cli_test_env();
+    fs::write(
+        temp_dir.path().join("config.toml"),
+        "default_project = \"acme\"\ndefault_period = \"today\"\n",
+    )
+    .unwrap();
+
+    // start falls back to the configured project
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["start", "configured"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("acme"));
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("stop").assert().success();
+
+    // --project wins over the config
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["start", "explicit", "--project", "beta"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("beta"));
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("stop").assert().success();
+
+    // report without a period uses default_period
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "--format", "csv"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("configured"))
+        .stdout(predicate::str::contains(",acme"))
+        .stdout(predicate::str::contains(",beta"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_add_entry_forms() {
+    let temp_dir = setup_cli_test_env();
+
+    for (extra, expected_ms) in [
+        (vec!["--start", "09:00", "--end", "11:30"], 9_000_000),
+        (vec!["--start", "09:00", "--duration", "2h30m"], 9_000_000),
+        (vec!["--end", "11:30", "--duration", "1:15"], 4_500_000),
+        (
+            vec!["--start", "09:00", "--end", "10:00", "--duration", "60"],
+            3_600_000,
+        ),
+    ] {
+        let mut cmd = Command::cargo_bin("timelog").unwrap();
+        cmd.args(["add", "--task", "after the fact", "--date", "2024-06-01"])
+            .args(&extra)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("recorded"));
+
+        let contents = fs::read_to_string(temp_dir.path().join("records.csv")).unwrap();
+        let last: Vec<&str> = contents.lines().last().unwrap().split(',').collect();
+        assert_eq!(
+            last[..6],
+            [
+                "after the fact",
+                &expected_ms.to_string(),
+                "2024-06-01",
+                "",
+                "",
+                ""
+            ]
+        );
+        let timestamp = |c: &str| chrono::DateTime::parse_from_rfc3339(c).unwrap();
+        assert_eq!(
+            (timestamp(last[7]) - timestamp(last[6])).num_milliseconds(),
+            expected_ms
+        );
+    }
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_add_conflicting_values() {
+    let temp_dir = setup_cli_test_env();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args([
+        "add",
+        "--task",
+        "x",
+        "--start",
+        "09:00",
+        "--end",
+        "11:30",
+        "--duration",
+        "2h",
+    ])
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("--duration is"));
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["add", "--task", "x", "--start", "09:00"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("two of"));
+
+    assert!(!temp_dir.path().join("records.csv").exists());
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_projects_only() {
+    let temp_dir = setup_cli_test_env();
+
+    let today = chrono::Utc::now().date_naive();
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        format!(
+            "task,duration_ms,date,project\ndesign,1800000,{today},acme\nreview,600000,{today},\nplanning,900000,{today},acme\n"
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today", "--projects-only"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("PROJECT"))
+        .stdout(predicate::str::is_match(r"acme\s+2\s+00h45m").unwrap())
+        .stdout(predicate::str::is_match(r"\(no project\)\s+1\s+00h10m").unwrap())
+        .stdout(predicate::str::contains("design").not())
+        .stdout(predicate::str::contains("review").not())
+        .stdout(predicate::str::contains("planning").not());
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_amend_dry_run_shows_before_and_after() {
+    let temp_dir = setup_cli_test_env();
+
+    let record_file = temp_dir.path().join("records.csv");
+    let contents = "task,duration_ms,date,project\ninvoice prep,3600000,2024-01-15,acme\n";
+    fs::write(&record_file, contents).unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args([
+        "amend",
+        "--date",
+        "2024-01-15",
+        "--task",
+        "invoice",
+        "--new-duration",
+        "90",
+        "--new-project",
+        "",
+        "--dry-run",
+    ])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains(
+        "- task: invoice prep  duration: 01:00:00.000  date: 2024-01-15  project: acme",
+    ))
+    .stdout(predicate::str::contains(
+        "+ task: invoice prep  duration: 01:30:00.000  date: 2024-01-15  project: (none)",
+    ));
+
+    assert_eq!(fs::read_to_string(&record_file).unwrap(), contents);
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_notes() {
+    let temp_dir = setup_cli_test_env();
+
+    let record_file = temp_dir.path().join("records.csv");
+    // an older 4-column file keeps loading, with no notes
+    fs::write(
+        &record_file,
+        "task,duration_ms,date,project\nbugfix,600000,2024-01-15,acme\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["start", "auth", "--note", "fixed the auth bug"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("stop").assert().success();
+
+    let contents = fs::read_to_string(&record_file).unwrap();
+    let last: Vec<&str> = contents.lines().last().unwrap().split(',').collect();
+    assert_eq!(last[4], "fixed the auth bug");
+
+    // hidden by default, shown with --show-notes
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("fixed the auth bug").not());
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today", "--show-notes"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("NOTE"))
+        .stdout(predicate::str::contains("fixed the auth bug"));
+
+    // amend --new-note edits the old record
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args([
+        "amend",
+        "--date",
+        "2024-01-15",
+        "--task",
+        "bugfix",
+        "--new-note",
+        "PR #42",
+    ])
+    .assert()
+    .success();
+    let contents = fs::read_to_string(&record_file).unwrap();
+    assert!(contents.contains("bugfix,600000,2024-01-15,acme,PR #42"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_projects_and_tasks_json() {
+    let temp_dir = setup_cli_test_env();
+
+    // nothing recorded yet is still a valid, empty document
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    let output = cmd.args(["projects", "--json"]).output().unwrap();
+    assert!(output.status.success());
+    let projects: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(projects, serde_json::json!([]));
+
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        "task,duration_ms,date,project\ndesign,3600000,2024-01-15,acme\nreview,600000,2024-01-16,acme\ndesign,900000,2024-01-17,\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    let output = cmd.args(["projects", "--json"]).output().unwrap();
+    assert!(output.status.success());
+    let projects: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        projects,
+        serde_json::json!([
+            {"name": "acme", "total_ms": 4200000, "count": 2, "last_used": "2024-01-16"}
+        ])
+    );
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    let output = cmd.args(["tasks", "--json"]).output().unwrap();
+    assert!(output.status.success());
+    let tasks: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(tasks, serde_json::json!(["design", "review"]));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_tags() {
+    let temp_dir = setup_cli_test_env();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["start", "standup", "--tag", "#meeting", "--tag", "team"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("stop").assert().success();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["start", "refactor", "--tag", "deep-work"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("stop").assert().success();
+
+    let contents = fs::read_to_string(temp_dir.path().join("records.csv")).unwrap();
+    assert_eq!(
+        contents.lines().nth(1).unwrap().split(',').nth(5),
+        Some("meeting;team")
+    );
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today", "--tag", "meeting"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("standup"))
+        .stdout(predicate::str::contains("refactor").not());
+
+    // repeated --tag requires all of them
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today", "--tag", "meeting", "--tag", "deep-work"])
+        .assert()
+        .code(3)
+        .stderr(predicate::str::contains("no records in selected period"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_format_from_config() {
+    let temp_dir = setup_cli_test_env();
+
+    let today = chrono::Utc::now().date_naive();
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        format!("task,duration_ms,date,project\ndesign,3600000,{today},acme\n"),
+    )
+    .unwrap();
+    fs::write(
+        temp_dir.path().join("config.toml"),
+        "[report]\nformat = \"json\"\n",
+    )
+    .unwrap();
+
+    // no flag: the configured format
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    let output = cmd.args(["report", "today"]).output().unwrap();
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["total_ms"], 3600000);
+
+    // the flag still wins
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today", "--format", "csv"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("task,duration_ms"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_cancel() {
+    let temp_dir = setup_cli_test_env();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("cancel")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("no task to cancel"));
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["start", "mistake"]).assert().success();
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("cancel")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("discarded mistake (elapsed "));
+
+    assert!(!temp_dir.path().join("state.json").exists());
+    assert!(!temp_dir.path().join("records.csv").exists());
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_min_date_clamp() {
+    let temp_dir = setup_cli_test_env();
+
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        "task,duration_ms,date,project\nimported junk,3600000,2023-12-31,\nreal work,1800000,2024-01-02,\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args([
+        "report",
+        "--from",
+        "2023-12-01",
+        "--to",
+        "2024-01-31",
+        "--min-date",
+        "2024-01-01",
+        "--format",
+        "csv",
+    ])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("real work"))
+    .stdout(predicate::str::contains("imported junk").not());
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_status_watch_emits_on_change() {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+
+    let _temp_dir = setup_cli_test_env();
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_timelog"))
+        .args(["status", "--watch"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let stdout = child.stdout.take().unwrap();
+    let (tx, rx) = std::sync::mpsc::channel();
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            if tx.send(line.unwrap()).is_err() {
+                break;
+            }
+        }
+    });
+    let next_line = || {
+        rx.recv_timeout(Duration::from_secs(5))
+            .expect("no status line emitted")
+    };
+
+    assert_eq!(next_line(), r#"{"active":false}"#);
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["start", "watched"]).assert().success();
+    let line: serde_json::Value = serde_json::from_str(&next_line()).unwrap();
+    assert_eq!(line["active"], true);
+    assert_eq!(line["task"], "watched");
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("stop").assert().success();
+    assert_eq!(next_line(), r#"{"active":false}"#);
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_exclude_project() {
+    let temp_dir = setup_cli_test_env();
+
+    let today = chrono::Utc::now().date_naive();
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        format!(
+            "task,duration_ms,date,project\n\
+             design,3600000,{today},acme\n\
+             standup,600000,{today},internal\n\
+             review,1800000,{today},beta\n"
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today", "--exclude-project", "internal"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("design"))
+        .stdout(predicate::str::contains("review"))
+        .stdout(predicate::str::contains("standup").not());
+
+    // include first, then exclude
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args([
+        "report",
+        "today",
+        "--project",
+        "acme",
+        "--exclude-project",
+        "acme",
+    ])
+    .assert()
+    .code(3)
+    .stderr(predicate::str::contains("no records in selected period"));
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["export", "today", "--exclude-project", "internal"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("design,3600000"))
+        .stdout(predicate::str::contains("review,1800000"))
+        .stdout(predicate::str::contains("standup").not());
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_summary_line() {
+    let temp_dir = setup_cli_test_env();
+
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        "task,duration_ms,date,project\n\
+         design,7200000,2024-01-15,acme\n\
+         review,2700000,2024-01-15,acme\n\
+         design,3600000,2024-01-16,beta\n\
+         admin,1800000,2024-01-17,\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args([
+        "report",
+        "--from",
+        "2024-01-15",
+        "--to",
+        "2024-01-21",
+        "--summary-line",
+    ])
+    .assert()
+    .success()
+    .stdout(predicate::str::diff(
+        "Custom: 04h15m across 2 projects, 3 tasks, 3 days\n",
+    ));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_billable() {
+    let temp_dir = setup_cli_test_env();
+
+    fs::write(
+        temp_dir.path().join("config.toml"),
+        "[billing]\ncurrency = \"$\"\n\n[billing.rates]\nacme = 120.0\n",
+    )
+    .unwrap();
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        "task,duration_ms,date,project\n\
+         design,5400000,2024-01-15,acme\n\
+         review,1800000,2024-01-15,beta\n",
+    )
+    .unwrap();
+    let report = |extra: &[&str]| {
+        let mut cmd = Command::cargo_bin("timelog").unwrap();
+        cmd.args([
+            "report",
+            "--from",
+            "2024-01-15",
+            "--to",
+            "2024-01-15",
+            "--billable",
+        ])
+        .args(extra)
+        .assert()
+        .success()
+    };
+
+    // beta has no rate and no default is configured
+    report(&[])
+        .stdout(predicate::str::contains("AMOUNT"))
+        .stdout(predicate::str::is_match(r"design .* \$180\.00").unwrap())
+        .stdout(predicate::str::is_match(r"(?m)^review .* -$").unwrap())
+        .stdout(predicate::str::is_match(r"TOTAL .* \$180\.00").unwrap());
+
+    // --rate stands in for the default rate
+    report(&["--rate", "100"])
+        .stdout(predicate::str::is_match(r"review .* \$50\.00").unwrap())
+        .stdout(predicate::str::is_match(r"TOTAL .* \$230\.00").unwrap());
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_total_per_tag() {
+    let temp_dir = setup_cli_test_env();
+
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        "task,duration_ms,date,project,note,tags\n\
+         design,3600000,2024-01-15,acme,,billable;deep-work\n\
+         call,1800000,2024-01-15,beta,,billable\n\
+         admin,900000,2024-01-15,,,\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args([
+        "report",
+        "--from",
+        "2024-01-15",
+        "--to",
+        "2024-01-15",
+        "--total-per-tag",
+    ])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("By tag"))
+    .stdout(predicate::str::is_match(r"#billable +01h30m").unwrap())
+    .stdout(predicate::str::is_match(r"#deep-work +01h00m").unwrap());
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_round_scope() {
+    let temp_dir = setup_cli_test_env();
+
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        "task,duration_ms,date,project\n\
+         design,300000,2024-01-15,acme\n\
+         review,300000,2024-01-15,acme\n\
+         call,1200000,2024-01-15,beta\n",
+    )
+    .unwrap();
+
+    for (scope, total_ms) in [
+        ("record", 3_600_000),
+        ("group", 2_700_000),
+        ("total", 1_800_000),
+    ] {
+        let mut cmd = Command::cargo_bin("timelog").unwrap();
+        let output = cmd
+            .args([
+                "report",
+                "--from",
+                "2024-01-15",
+                "--to",
+                "2024-01-15",
+                "--group-by",
+                "project",
+                "--round",
+                "15m",
+                "--round-scope",
+                scope,
+                "--format",
+                "json",
+            ])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(report["total_ms"], total_ms, "scope {scope}");
+    }
+
+    // per-record amounts can't follow a total rounded as a whole
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args([
+        "report",
+        "--from",
+        "2024-01-15",
+        "--to",
+        "2024-01-15",
+        "--billable",
+        "--rate",
+        "100",
+        "--round",
+        "15m",
+        "--round-scope",
+        "total",
+    ])
+    .assert()
+    .code(2)
+    .stderr(predicate::str::contains("--round-scope record"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_corrupt_rows_warn_or_fail_with_strict() {
+    let temp_dir = setup_cli_test_env();
+
+    let record_file = temp_dir.path().join("records.csv");
+    let contents = "task,duration_ms,date,project\n\
+                    design,3600000,2024-01-15,acme\n\
+                    garbage,,not a date,\n";
+    fs::write(&record_file, contents).unwrap();
+
+    let report = ["report", "--from", "2024-01-15", "--to", "2024-01-15"];
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(report)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("design"))
+        .stderr(predicate::str::contains("skipping line 3"));
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("--strict")
+        .args(report)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("line 3"));
+
+    // rewriting the file would drop the bad row, so amend refuses
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["amend", "--last", "--new-duration", "30m"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("fix or remove it first"));
+    assert_eq!(fs::read_to_string(&record_file).unwrap(), contents);
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_import_csv() {
+    let temp_dir = setup_cli_test_env();
+
+    let import_file = temp_dir.path().join("spreadsheet.csv");
+    fs::write(
+        &import_file,
+        "task,duration_ms,date,project\n\
+         design,3600000,2024-01-15,acme\n\
+         oops,0,2024-01-15,\n\
+         review,1800000,15/01/2024,\n\
+         admin,900000,2024-01-16,\n",
+    )
+    .unwrap();
+    let record_file = temp_dir.path().join("records.csv");
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["import", import_file.to_str().unwrap(), "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "would import 2 records, skipped 2",
+        ))
+        .stderr(predicate::str::contains(
+            "skipping line 3: duration must be positive",
+        ))
+        .stderr(predicate::str::contains("skipping line 4: Invalid date"));
+    assert!(!record_file.exists());
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["import", import_file.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("imported 2 records, skipped 2"));
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["tasks"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("design"))
+        .stdout(predicate::str::contains("admin"))
+        .stdout(predicate::str::contains("oops").not());
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_export_ics() {
+    let temp_dir = setup_cli_test_env();
+
+    let today = chrono::Utc::now().date_naive();
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        format!(
+            "task,duration_ms,date,project\n\
+             design,3600000,{today},acme\n\
+             admin,900000,{today},\n\
+             old,900000,2020-01-01,\n"
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    let output = cmd
+        .args(["export", "today", "--format", "ics"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let ics = String::from_utf8(output.stdout).unwrap();
+    assert!(ics.starts_with("BEGIN:VCALENDAR"));
+    assert_eq!(ics.matches("BEGIN:VEVENT").count(), 2);
+    assert_eq!(ics.matches("END:VEVENT").count(), 2);
+    assert!(ics.contains("SUMMARY:design"));
+    assert!(ics.contains("DESCRIPTION:project: acme"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+#[cfg(unix)]
+fn test_report_into_closed_pipe() {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+
+    let temp_dir = setup_cli_test_env();
+
+    let today = chrono::Utc::now().date_naive();
+    let mut csv = String::from("task,duration_ms,date,project\n");
+    for i in 0..5000 {
+        csv.push_str(&format!("task{i},60000,{today},\n"));
+    }
+    fs::write(temp_dir.path().join("records.csv"), csv).unwrap();
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_timelog"))
+        .args(["report", "today"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // read one line like `head -1`, then hang up
+    let mut first = String::new();
+    BufReader::new(child.stdout.take().unwrap())
+        .read_line(&mut first)
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!stderr.contains("panicked"), "stderr: {stderr}");
+    assert!(!stderr.contains("failed printing to stdout"));
+    assert_eq!(output.status.code(), Some(0));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+#[cfg(unix)]
+fn test_upload_survives_a_plugin_that_ignores_its_input() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = setup_cli_test_env();
+
+    let today = chrono::Utc::now().date_naive();
+    let mut csv = String::from("task,duration_ms,date,project\n");
+    for i in 0..3000 {
+        csv.push_str(&format!("task{i},60000,{today},\n"));
+    }
+    fs::write(temp_dir.path().join("records.csv"), csv).unwrap();
+    let plugins = temp_dir.path().join("plugins");
+    fs::create_dir_all(&plugins).unwrap();
+    for (name, script) in [
+        ("quick", "#!/bin/sh\nexit 1\n"),
+        (
+            "steady",
+            "#!/bin/sh\ncat > /dev/null\necho '{\"success\": true, \"uploaded_count\": 3000, \"message\": \"sent\", \"errors\": []}'\n",
+        ),
+    ] {
+        let path = plugins.join(format!("timelog-{name}"));
+        fs::write(&path, script).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    // the quick plugin exits without reading stdin; that is its failure, not ours
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["upload", "today", "--plugin", "quick", "--plugin", "steady"])
+        .assert()
+        .code(4)
+        .stderr(predicate::str::contains("Plugin failed with exit code"))
+        .stdout(predicate::str::contains("quick: FAILED"))
+        .stdout(predicate::str::contains("steady: ok, 3000 records"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+fn test_completions_bash() {
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["completions", "bash"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("timelog"))
+        .stdout(predicate::str::contains("report"));
+}
+
+#[test]
+#[serial]
+fn test_report_from_file() {
+    let temp_dir = setup_cli_test_env();
+
+    let today = chrono::Utc::now().date_naive();
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        format!("task,duration_ms,date,project\nmain task,3600000,{today},\n"),
+    )
+    .unwrap();
+    let other = temp_dir.path().join("other.csv");
+    fs::write(
+        &other,
+        format!("task,duration_ms,date,project\nother task,1800000,{today},acme\n"),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today", "--from-file", other.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("other task"))
+        .stdout(predicate::str::contains("main task").not());
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["export", "today", "--from-file", other.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("other task,1800000"));
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["stats", "today", "--from-file", other.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("00h30m"))
+        .stdout(predicate::str::contains("01h00m").not());
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["stats", "today", "--from-file", "missing.csv"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("missing.csv: no such file"));
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today", "--from-file", "missing.csv"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("missing.csv: no such file"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_restart() {
+    let _temp_dir = setup_cli_test_env();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("restart")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("no records to restart from"));
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["start", "write docs", "--project", "acme"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("stop").assert().success();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("restart")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("restarted write docs"));
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("status")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("write docs"))
+        .stdout(predicate::str::contains("acme"));
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("restart")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("already in progress"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_amend_scale_duration() {
+    let temp_dir = setup_cli_test_env();
+
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        "task,duration_ms,date,project\n\
+         design,3600000,2024-01-15,acme\n\
+         admin,3600000,2024-01-15,\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args([
+        "amend",
+        "--date",
+        "2024-01-15",
+        "--scale-duration",
+        "0.5",
+        "--project",
+        "acme",
+        "--dry-run",
+    ])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains(
+        "Total: 01:00:00.000 → 00:30:00.000",
+    ));
+    let contents = fs::read_to_string(temp_dir.path().join("records.csv")).unwrap();
+    assert!(contents.contains("design,3600000,"));
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args([
+        "amend",
+        "--date",
+        "2024-01-15",
+        "--scale-duration",
+        "0.5",
+        "--project",
+        "acme",
+    ])
+    .assert()
+    .success();
+    let contents = fs::read_to_string(temp_dir.path().join("records.csv")).unwrap();
+    assert!(contents.contains("design,1800000,"));
+    assert!(contents.contains("admin,3600000,"));
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["amend", "--date", "2024-01-15", "--scale-duration", "0"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("must be positive"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_stop_idle_warning() {
+    let temp_dir = setup_cli_test_env();
+
+    let started = chrono::Utc::now() - chrono::Duration::hours(10);
+    fs::write(
+        temp_dir.path().join("state.json"),
+        format!(
+            r#"{{"timestamp":"{}","task":"forgotten","active":true,"project":null}}"#,
+            started.to_rfc3339()
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("status")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("over the 8h idle threshold"));
+
+    // not a terminal, so stop warns but records without prompting
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("stop")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("over the 8h idle threshold"));
+    let contents = fs::read_to_string(temp_dir.path().join("records.csv")).unwrap();
+    assert!(contents.contains("forgotten"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_highlight_task() {
+    let temp_dir = setup_cli_test_env();
+
+    let today = chrono::Utc::now().date_naive();
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        format!(
+            "task,duration_ms,date,project\n\
+             deploy api,3600000,{today},\n\
+             email,900000,{today},\n"
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    let output = cmd
+        .args([
+            "--color",
+            "always",
+            "report",
+            "today",
+            "--highlight-task",
+            "deploy",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let deploy = stdout.lines().find(|l| l.contains("deploy api")).unwrap();
+    let email = stdout.lines().find(|l| l.contains("email")).unwrap();
+    assert!(deploy.starts_with("\x1b[1mdeploy api"));
+    assert!(!email.contains("\x1b["));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_projects_table() {
+    let temp_dir = setup_cli_test_env();
+
+    let today = chrono::Utc::now().date_naive();
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        format!(
+            "task,duration_ms,date,project\n\
+             design,3600000,{today},acme\n\
+             review,1800000,{today},acme\n\
+             build,7200000,{today},beta\n\
+             email,900000,{today},\n\
+             legacy,3600000,2020-01-01,gamma\n"
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    let output = cmd
+        .args(["projects", "--period", "today"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let rows: Vec<&str> = stdout.lines().skip(1).collect();
+    assert_eq!(rows.len(), 3);
+    assert!(rows[0].starts_with("beta") && rows[0].contains("02h00m"));
+    assert!(rows[1].starts_with("acme") && rows[1].contains("01h30m"));
+    assert!(rows[2].starts_with("(no project)") && rows[2].contains("00h15m"));
+    assert!(!stdout.contains("gamma"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_tasks_by_project() {
+    let temp_dir = setup_cli_test_env();
+
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        "task,duration_ms,date,project\n\
+         review,600000,2024-01-15,acme\n\
+         design,3600000,2024-01-15,acme\n\
+         Design,900000,2024-01-16,acme\n\
+         design,900000,2024-01-17,acme\n\
+         build,900000,2024-01-17,beta\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    let output = cmd.args(["tasks", "--project", "acme"]).output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Design\ndesign\nreview\n"
+    );
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_complete_tasks() {
+    let temp_dir = setup_cli_test_env();
+
+    // a fresh install completes to nothing rather than an error
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    let output = cmd.arg("__complete_tasks").output().unwrap();
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.is_empty());
+
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        "task,duration_ms,date,project\n\
+         write docs,600000,2024-01-15,acme\n\
+         design,3600000,2024-01-15,acme\n\
+         write docs,900000,2024-01-16,\n",
+    )
+    .unwrap();
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    let output = cmd.arg("__complete_tasks").output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "design\nwrite docs\n"
+    );
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["completions", "bash"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("timelog __complete_tasks"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_empty_or_corrupt_state_file() {
+    let temp_dir = setup_cli_test_env();
+    let state_file = temp_dir.path().join("state.json");
+
+    // an interrupted write leaves an empty file, which counts as no task
+    fs::write(&state_file, "").unwrap();
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("status")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("no task to provide status"))
+        .stderr(predicate::str::contains("panicked").not());
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["start", "fresh"]).assert().success();
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("status")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("fresh"));
+
+    // garbage is reported rather than panicking, and cancel clears it
+    fs::write(&state_file, "{\"task\":").unwrap();
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("status")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("state file is corrupt"))
+        .stderr(predicate::str::contains("panicked").not());
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("cancel").assert().success();
+    assert!(!state_file.exists());
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_sort_duration_reverse() {
+    let temp_dir = setup_cli_test_env();
+
+    let today = chrono::Utc::now().date_naive();
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        format!(
+            "task,duration_ms,date,project\n\
+             short,600000,{today},\n\
+             long,7200000,{today},\n\
+             medium,1800000,{today},\n"
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    let output = cmd
+        .args(["report", "today", "--sort", "duration", "--reverse"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let position = |task: &str| stdout.find(&format!("\n{task} ")).unwrap();
+    assert!(position("long") < position("medium"));
+    assert!(position("medium") < position("short"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_top() {
+    let temp_dir = setup_cli_test_env();
+
+    let today = chrono::Utc::now().date_naive();
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        format!(
+            "task,duration_ms,date,project\n\
+             design,3600000,{today},\n\
+             email,300000,{today},\n\
+             review,1800000,{today},\n\
+             standup,600000,{today},\n\
+             design,600000,{today},\n"
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today", "--group-by", "task", "--top", "2"])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"design\s+2\s+01h10m").unwrap())
+        .stdout(predicate::str::is_match(r"review\s+1\s+00h30m").unwrap())
+        .stdout(predicate::str::is_match(r"\(other\)\s+2\s+00h15m").unwrap())
+        .stdout(predicate::str::contains("standup").not())
+        .stdout(predicate::str::is_match(r"TOTAL\s+5\s+01h55m").unwrap());
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today", "--top", "2"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--top needs --group-by"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_chart() {
+    let temp_dir = setup_cli_test_env();
+
+    let today = chrono::Utc::now().date_naive();
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        format!(
+            "task,duration_ms,date,project\n\
+             design,3600000,{today},acme\n\
+             review,1800000,{today},beta\n"
+        ),
+    )
+    .unwrap();
+
+    // piped, so fixed 40 columns of plain '#'
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today", "--group-by", "project", "--chart"])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"acme\s+1\s+01h00m  #{40}\n").unwrap())
+        .stdout(predicate::str::is_match(r"beta\s+1\s+00h30m  #{20}\n").unwrap())
+        .stdout(predicate::str::contains("█").not());
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["--color", "always", "report", "today", "--chart"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("█".repeat(40)));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_stop_at_backdates_the_end() {
+    let temp_dir = setup_cli_test_env();
+
+    let started = chrono::Utc::now() - chrono::Duration::hours(2);
+    fs::write(
+        temp_dir.path().join("state.json"),
+        format!(
+            r#"{{"timestamp":"{}","task":"review","active":true,"project":null}}"#,
+            started.to_rfc3339()
+        ),
+    )
+    .unwrap();
+
+    let too_early = started - chrono::Duration::minutes(5);
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["stop", "--at", &too_early.to_rfc3339()])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is before review started"));
+
+    let stopped = started + chrono::Duration::minutes(45);
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["stop", "--at", &stopped.to_rfc3339()])
+        .assert()
+        .success();
+
+    // 45 minutes, not the two hours the timer ran
+    let contents = fs::read_to_string(temp_dir.path().join("records.csv")).unwrap();
+    assert!(contents.contains("review,2700000,"), "{contents}");
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_no_project() {
+    let temp_dir = setup_cli_test_env();
+
+    let today = chrono::Utc::now().date_naive();
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        format!(
+            "task,duration_ms,date,project\n\
+             design,3600000,{today},acme\n\
+             email,900000,{today},\n"
+        ),
+    )
+    .unwrap();
+
+    for args in [
+        ["report", "today", "--no-project"],
+        ["report", "today", "--project="],
+    ] {
+        let mut cmd = Command::cargo_bin("timelog").unwrap();
+        let output = cmd.args(args).output().unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains("without a project"), "{stdout}");
+        assert!(stdout.contains("email"), "{stdout}");
+        assert!(!stdout.contains("design"), "{stdout}");
+    }
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_compare() {
+    let temp_dir = setup_cli_test_env();
+
+    let today = chrono::Utc::now().date_naive();
+    let last_week = today - chrono::Duration::days(7);
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        format!(
+            "task,duration_ms,date,project\n\
+             design,7200000,{today},acme\n\
+             design,3600000,{last_week},acme\n"
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    let output = cmd
+        .args(["report", "this-week", "--compare", "--color", "never"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let acme = stdout.lines().find(|l| l.starts_with("acme")).unwrap();
+    assert!(acme.contains("02h00m"), "{stdout}");
+    assert!(acme.contains("+01h00m"), "{stdout}");
+    assert!(acme.contains("+100%"), "{stdout}");
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today", "--compare"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--compare needs a period"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_quiet_and_verbose() {
+    let temp_dir = setup_cli_test_env();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    let output = cmd.args(["start", "deploy", "--quiet"]).output().unwrap();
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+
+    // the status line is the answer, not chatter
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["-q", "status"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("deploy"));
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["stop", "-v"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("recorded"))
+        .stderr(predicate::str::contains(format!(
+            "debug: state: {}",
+            temp_dir.path().join("state.json").display()
+        )));
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today", "-q"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("deploy"));
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["status", "-q", "-v"]).assert().failure();
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_status_json() {
+    let _temp_dir = setup_cli_test_env();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    let output = cmd.args(["status", "--json"]).output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "{\"active\":false}\n"
+    );
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["start", "deploy", "--project", "ops"])
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    let output = cmd.args(["status", "--json"]).output().unwrap();
+    assert!(output.status.success());
+    let status: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(status["active"], true);
+    assert_eq!(status["task"], "deploy");
+    assert_eq!(status["project"], "ops");
+    assert!(status["elapsed_ms"].as_i64().unwrap() >= 0);
+    assert!(chrono::DateTime::parse_from_rfc3339(status["since"].as_str().unwrap()).is_ok());
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_round_modes() {
+    let temp_dir = setup_cli_test_env();
+
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        "task,duration_ms,date,project\n\
+         call,420000,2024-01-15,acme\n",
+    )
+    .unwrap();
+
+    // a 7-minute call against 15-minute blocks
+    for (mode, total_ms) in [("up", 900_000), ("nearest", 0), ("down", 0)] {
+        let mut cmd = Command::cargo_bin("timelog").unwrap();
+        let output = cmd
+            .args([
+                "report",
+                "--from",
+                "2024-01-15",
+                "--to",
+                "2024-01-15",
+                "--round",
+                "15",
+                "--round-mode",
+                mode,
+                "--format",
+                "json",
+            ])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(report["total_ms"], total_ms, "mode {mode}");
+    }
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_amend_keeps_unknown_columns() {
+    let temp_dir = setup_cli_test_env();
+
+    let start = "2024-01-15T09:00:00.000Z";
+    let end = "2024-01-15T10:00:00.000Z";
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        format!(
+            "task,duration_ms,date,project,note,tags,start,end,invoice\n\
+             design,3600000,2024-01-15,acme,,,{start},{end},INV-7\n\
+             review,3600000,2024-01-15,acme,,,{start},{end},INV-8\n"
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args([
+        "amend",
+        "--date",
+        "2024-01-15",
+        "--task",
+        "review",
+        "--new-task",
+        "code review",
+    ])
+    .assert()
+    .success();
+
+    let contents = fs::read_to_string(temp_dir.path().join("records.csv")).unwrap();
+    assert_eq!(
+        contents,
+        format!(
+            "task,duration_ms,date,project,note,tags,start,end,invoice\n\
+             design,3600000,2024-01-15,acme,,,{start},{end},INV-7\n\
+             code review,3600000,2024-01-15,acme,,,{start},{end},INV-8\n"
+        )
+    );
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_amend_backs_up_the_record_file() {
+    let temp_dir = setup_cli_test_env();
+
+    let before = "task,duration_ms,date,project,note,tags,start,end\n\
+                  review,3600000,2024-01-15,acme,,,2024-01-15T09:00:00.000Z,2024-01-15T10:00:00.000Z\n";
+    fs::write(temp_dir.path().join("records.csv"), before).unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args([
+        "amend",
+        "--date",
+        "2024-01-15",
+        "--task",
+        "review",
+        "--new-task",
+        "code review",
+    ])
+    .assert()
+    .success();
+
+    let backups: Vec<_> = fs::read_dir(temp_dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| {
+            p.file_name()
+                .unwrap()
+                .to_string_lossy()
+                .starts_with("records.csv.bak-")
+        })
+        .collect();
+    assert_eq!(backups.len(), 1, "{backups:?}");
+    assert_eq!(fs::read_to_string(&backups[0]).unwrap(), before);
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_doctor_fails_on_corrupt_state() {
+    let temp_dir = setup_cli_test_env();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("doctor")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("PASS  records: none recorded yet"))
+        .stdout(predicate::str::contains("PASS  state: no task running"));
+
+    fs::write(temp_dir.path().join("state.json"), "not json").unwrap();
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("doctor")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("FAIL  state:"))
+        .stderr(predicate::str::contains("1 check(s) failed"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_missing_home_is_a_clean_error() {
+    let _temp_dir = setup_cli_test_env();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.env_remove("HOME")
+        .env_remove("USERPROFILE")
+        .env_remove("TIMELOG_STATE_PATH")
+        .arg("status")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error: $HOME is not set"))
+        .stderr(predicate::str::contains("TIMELOG_STATE_PATH"))
+        .stderr(predicate::str::contains("panicked").not());
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_task_filter() {
+    let temp_dir = setup_cli_test_env();
+
+    let today = chrono::Utc::now().date_naive();
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        format!(
+            "task,duration_ms,date,project\n\
+             review:pr-123,3600000,{today},acme\n\
+             Review:PR-124,1800000,{today},acme\n\
+             review:pr-125,900000,{today},globex\n\
+             standup,900000,{today},acme\n"
+        ),
+    )
+    .unwrap();
+
+    for pattern in ["review:", "review:*"] {
+        let mut cmd = Command::cargo_bin("timelog").unwrap();
+        let output = cmd
+            .args(["report", "today", "--task", pattern, "--project", "acme"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains("review:pr-123"), "{stdout}");
+        assert!(stdout.contains("Review:PR-124"), "{stdout}");
+        assert!(!stdout.contains("review:pr-125"), "{stdout}");
+        assert!(!stdout.contains("standup"), "{stdout}");
+    }
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_named_timers_run_side_by_side() {
+    let temp_dir = setup_cli_test_env();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["start", "coding"]).assert().success();
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["start", "build", "--name", "ci"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("(timer ci)"));
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["start", "other", "--name", "ci"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("already in progress (timer ci)"));
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["pause", "--name", "ci"]).assert().success();
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("status")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("coding"))
+        .stdout(predicate::str::contains("build (timer ci)"));
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["status", "--name", "ci"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("paused"))
+        .stdout(predicate::str::contains("coding").not());
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["stop", "--name", "ci"]).assert().success();
+    let records = fs::read_to_string(temp_dir.path().join("records.csv")).unwrap();
+    assert!(records.contains("build,"), "{records}");
+    assert!(!records.contains("coding,"), "{records}");
+
+    // the default timer is untouched
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["status", "--json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"task\":\"coding\""));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_push_and_pop() {
+    let temp_dir = setup_cli_test_env();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("pop")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("the task stack is empty"));
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["start", "feature"]).assert().success();
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["push", "call"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "pushed feature aside (1 on the stack)",
+        ));
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["push", "incident"]).assert().success();
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("status")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("incident"))
+        .stdout(predicate::str::contains(
+            "2 suspended, next up first: call, feature",
+        ));
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("pop")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "resumed call (1 left on the stack)",
+        ));
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("pop")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "resumed feature (0 left on the stack)",
+        ));
+
+    let records = fs::read_to_string(temp_dir.path().join("records.csv")).unwrap();
+    assert!(records.contains("incident,"), "{records}");
+    assert!(records.contains("call,"), "{records}");
+    assert!(!records.contains("feature,"), "{records}");
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["status", "--json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"task\":\"feature\""));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_exit_codes() {
+    let temp_dir = setup_cli_test_env();
+
+    // nothing recorded yet
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today"])
+        .assert()
+        .code(3)
+        .stderr(predicate::str::contains("no records in selected period"));
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("restart").assert().code(3);
+    // an empty JSON report is still a document
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today", "--format", "json"])
+        .assert()
+        .success();
+
+    let today = chrono::Utc::now().date_naive();
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        format!("task,duration_ms,date,project\ndesign,3600000,{today},acme\n"),
+    )
+    .unwrap();
+
+    // bad arguments, from clap and from timelog's own checks
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "someday"]).assert().code(2);
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today", "--group-by", "task", "--top", "0"])
+        .assert()
+        .code(2);
+
+    // a plugin that fails
+    let plugin = temp_dir.path().join("plugins").join("timelog-broken");
+    fs::write(&plugin, "#!/bin/sh\ncat > /dev/null\nexit 1\n").unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&plugin, fs::Permissions::from_mode(0o755)).unwrap();
+        let mut cmd = Command::cargo_bin("timelog").unwrap();
+        cmd.args(["upload", "today", "--plugin", "broken"])
+            .assert()
+            .code(4);
+    }
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("--help")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Exit codes:"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_daily_totals_fill_gaps() {
+    let temp_dir = setup_cli_test_env();
+
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        "task,duration_ms,date,project\n\
+         design,3600000,2024-01-17,acme\n\
+         review,1800000,2024-01-15,acme\n\
+         standup,900000,2024-01-15,\n",
+    )
+    .unwrap();
+    let args = [
+        "report",
+        "--from",
+        "2024-01-15",
+        "--to",
+        "2024-01-17",
+        "--group-by",
+        "day",
+    ];
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    let output = cmd.args(args).output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(!stdout.contains("2024-01-16 "), "{stdout}");
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    let output = cmd.args(args).arg("--fill-gaps").output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout
+        .lines()
+        .filter(|l| l.starts_with("2024-01-1") || l.starts_with("TOTAL"))
+        .collect();
+    assert_eq!(lines.len(), 4, "{stdout}");
+    assert!(lines[0].starts_with("2024-01-15") && lines[0].contains("00h45m"));
+    assert!(lines[1].starts_with("2024-01-16") && lines[1].contains("00h00m"));
+    assert!(lines[2].starts_with("2024-01-17") && lines[2].contains("01h00m"));
+    assert!(lines[3].contains("01h45m"), "{stdout}");
+
+    // only day grouping has dates to fill
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "--from", "2024-01-15", "--to", "2024-01-17"])
+        .args(["--group-by", "task", "--fill-gaps"])
+        .assert()
+        .code(2);
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_start_pomodoro_without_a_terminal_just_starts() {
+    let _temp_dir = setup_cli_test_env();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["start", "focus", "--pomodoro"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("started"))
+        .stderr(predicate::str::contains("--pomodoro needs a terminal"));
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.arg("status")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("focus"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_truncated_state_file_is_a_clean_error() {
+    let temp_dir = setup_cli_test_env();
+    let state_file = temp_dir.path().join("state.json");
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["start", "build", "--name", "ci"])
+        .assert()
+        .success();
+    fs::write(&state_file, "{ \"timestamp\": ").unwrap();
+    for command in ["pause", "resume", "stop", "status"] {
+        let mut cmd = Command::cargo_bin("timelog").unwrap();
+        cmd.arg(command)
+            .assert()
+            .code(1)
+            .stderr(predicate::str::contains("state file is corrupt"))
+            .stderr(predicate::str::contains("run `timelog cancel` to reset"))
+            .stderr(predicate::str::contains("panicked").not());
+    }
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["cancel", "--force"]).assert().success();
+    assert!(!state_file.exists());
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["cancel", "--force"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("no task to cancel"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_total_only() {
+    let temp_dir = setup_cli_test_env();
+
+    let today = chrono::Utc::now().date_naive();
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        format!(
+            "task,duration_ms,date,project\n\
+             design,7200000,{today},acme\n\
+             review,1800000,{today},acme\n\
+             admin,3600000,{today},globex\n"
+        ),
+    )
+    .unwrap();
+    fs::write(
+        temp_dir.path().join("config.toml"),
+        "[billing]\ncurrency = \"$\"\ndefault_rate = 100.0\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today", "--total-only", "--project", "acme"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Today report"))
+        .stdout(predicate::str::contains("TOTAL  02h30m"))
+        .stdout(predicate::str::contains("design").not())
+        .stdout(predicate::str::contains("review").not())
+        .stdout(predicate::str::contains("TASK").not());
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args([
+        "report",
+        "today",
+        "--total-only",
+        "--billable",
+        "--task",
+        "admin",
+    ])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("TOTAL  01h00m  $100.00"))
+    .stdout(predicate::str::contains("globex").not());
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today", "--total-only", "--format", "json"])
+        .assert()
+        .code(2);
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_check_overlaps() {
+    let temp_dir = setup_cli_test_env();
+
+    let today = chrono::Utc::now().date_naive();
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        format!(
+            "task,duration_ms,date,project,note,tags,start,end\n\
+             design,5400000,{today},acme,,,{today}T09:00:00Z,{today}T10:30:00Z\n\
+             review,4500000,{today},acme,,,{today}T09:45:00Z,{today}T11:00:00Z\n\
+             lunch,3600000,{today},,,,{today}T11:00:00Z,{today}T12:00:00Z\n"
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today", "--check-overlaps"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "records overlap by 00h45m: design (acme)",
+        ))
+        .stderr(predicate::str::contains("and review (acme)"))
+        .stderr(predicate::str::contains("lunch").not());
+
+    // without the flag nothing is checked
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("overlap").not());
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_since_relative_range() {
+    let temp_dir = setup_cli_test_env();
+
+    let today = chrono::Utc::now().date_naive();
+    let days_ago = |n: u64| today - chrono::Days::new(n);
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        format!(
+            "task,duration_ms,date,project\n\
+             design,3600000,{today},acme\n\
+             review,1800000,{},acme\n\
+             admin,900000,{},globex\n\
+             archived,7200000,{},acme\n",
+            days_ago(2),
+            days_ago(1),
+            days_ago(10)
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args([
+        "report",
+        "--since",
+        "3d",
+        "--group-by",
+        "task",
+        "--project",
+        "acme",
+    ])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains(format!(
+        "({}..{today})",
+        days_ago(3)
+    )))
+    .stdout(predicate::str::contains("design"))
+    .stdout(predicate::str::contains("review"))
+    .stdout(predicate::str::contains("admin").not())
+    .stdout(predicate::str::contains("archived").not());
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "--since", "2w"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("archived"));
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "--since", "3y"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("invalid --since '3y'"));
+
+    cleanup_cli_test_env();
+}
+
+#[test]
+#[serial]
+fn test_report_hours_decimal() {
+    let temp_dir = setup_cli_test_env();
+
+    let today = chrono::Utc::now().date_naive();
+    fs::write(
+        temp_dir.path().join("records.csv"),
+        format!(
+            "task,duration_ms,date,project\n\
+             design,5400000,{today},acme\n\
+             review,2700000,{today},acme\n"
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today", "--hours-decimal"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1.50"))
+        .stdout(predicate::str::contains("0.75"))
+        .stdout(predicate::str::is_match(r"TOTAL\s+2\.25").unwrap())
+        .stdout(predicate::str::contains("01h30m").not());
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today", "--group-by", "task", "--hours-decimal"])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"TOTAL\s+2\s+2\.25").unwrap());
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["stats", "today", "--hours-decimal"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("total          2.25"));
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today", "--summary-line", "--hours-decimal"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Today: 2.25 across 1 project"));
+
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "this-week", "--compare", "--hours-decimal"])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"acme\s+2\.25\s+0\.00\s+\+2\.25").unwrap())
+        .stdout(predicate::str::contains("02h15m").not());
+
+    // the default is unchanged
+    let mut cmd = Command::cargo_bin("timelog").unwrap();
+    cmd.args(["report", "today"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("02h15m"));
+
+    cleanup_cli_test_env();
+}
@@ -1,4 +1,4 @@
-use chrono::{NaiveDate, Utc};
+use chrono::{NaiveDate, TimeZone, Utc};
 use serial_test::serial;
 use std::env;
 use std::fs;
@@ -17,6 +17,8 @@ fn setup_test_env() -> TempDir {
         env::set_var("TIMELOG_RECORD_PATH", format!("{temp_path}/records.csv"));
         env::set_var("TIMELOG_STATE_PATH", format!("{temp_path}/state.json"));
         env::set_var("TIMELOG_PLUGIN_PATH", format!("{temp_path}/plugins"));
+        env::set_var("TIMELOG_CONFIG_PATH", format!("{temp_path}/config.toml"));
+        env::set_var("TIMELOG_EVENTS_PATH", format!("{temp_path}/events.jsonl"));
     }
 
     // Create plugins directory
@@ -31,6 +33,8 @@ fn cleanup_test_env() {
         env::remove_var("TIMELOG_RECORD_PATH");
         env::remove_var("TIMELOG_STATE_PATH");
         env::remove_var("TIMELOG_PLUGIN_PATH");
+        env::remove_var("TIMELOG_CONFIG_PATH");
+        env::remove_var("TIMELOG_EVENTS_PATH");
     }
 }
 
@@ -45,22 +49,24 @@ fn test_state_operations() {
         task: "test task".to_string(),
         active: true,
         project: Some("test project".to_string()),
+        note: None,
+        tags: Vec::new(),
     };
 
     // Save state
-    assert!(save_state(&original_state).is_ok());
+    assert!(save_state(DEFAULT_TIMER, &original_state).is_ok());
 
     // Load state
-    let loaded_state = load_state().expect("Failed to load state");
+    let loaded_state = load_state(DEFAULT_TIMER).expect("Failed to load state");
     assert_eq!(loaded_state.task, original_state.task);
     assert_eq!(loaded_state.active, original_state.active);
     assert_eq!(loaded_state.project, original_state.project);
 
     // Delete state
-    assert!(delete_state().is_ok());
+    assert!(delete_state(DEFAULT_TIMER).is_ok());
 
     // Verify state is deleted
-    assert!(load_state().is_err());
+    assert!(load_state(DEFAULT_TIMER).is_err());
 
     cleanup_test_env();
 }
@@ -70,19 +76,19 @@ fn test_state_operations() {
 fn test_record_operations() {
     let _temp_dir = setup_test_env();
 
-    let record1 = Record {
-        task: "task1".to_string(),
-        duration_ms: 3600000, // 1 hour
-        date: NaiveDate::from_ymd_opt(2024, 1, 15).unwrap(),
-        project: Some("project1".to_string()),
-    };
+    let record1 = Record::new(
+        "task1",
+        3600000, // 1 hour
+        NaiveDate::from_ymd_opt(2024, 1, 15).unwrap(),
+        Some("project1".to_string()),
+    );
 
-    let record2 = Record {
-        task: "task2".to_string(),
-        duration_ms: 1800000, // 30 minutes
-        date: NaiveDate::from_ymd_opt(2024, 1, 16).unwrap(),
-        project: None,
-    };
+    let record2 = Record::new(
+        "task2",
+        1800000, // 30 minutes
+        NaiveDate::from_ymd_opt(2024, 1, 16).unwrap(),
+        None,
+    );
 
     // Save records
     assert!(save_record(&record1).is_ok());
@@ -155,7 +161,7 @@ fn test_backwards_compatibility() {
 fn test_plugin_discovery() {
     let _temp_dir = setup_test_env();
 
-    let plugin_dir = plugin_dir();
+    let plugin_dir = plugin_dir().unwrap();
 
     // Create some test plugins
     let plugin1_path = plugin_dir.join("timelog-test1");
@@ -183,7 +189,7 @@ fn test_plugin_discovery() {
     }
 
     // Discover plugins
-    let plugins = discover_plugins();
+    let plugins = discover_plugins().unwrap();
 
     // Should find exactly 2 plugins (excluding config files and non-executable files)
     assert_eq!(plugins.len(), 2);
@@ -200,24 +206,24 @@ fn test_plugin_discovery() {
 #[test]
 fn test_period_filtering() {
     let records = [
-        Record {
-            task: "task1".to_string(),
-            duration_ms: 3600000,
-            date: NaiveDate::from_ymd_opt(2024, 1, 15).unwrap(), // Monday
-            project: None,
-        },
-        Record {
-            task: "task2".to_string(),
-            duration_ms: 1800000,
-            date: NaiveDate::from_ymd_opt(2024, 1, 16).unwrap(), // Tuesday
-            project: None,
-        },
-        Record {
-            task: "task3".to_string(),
-            duration_ms: 2700000,
-            date: NaiveDate::from_ymd_opt(2024, 1, 17).unwrap(), // Wednesday
-            project: None,
-        },
+        Record::new(
+            "task1",
+            3600000,
+            NaiveDate::from_ymd_opt(2024, 1, 15).unwrap(), // Monday
+            None,
+        ),
+        Record::new(
+            "task2",
+            1800000,
+            NaiveDate::from_ymd_opt(2024, 1, 16).unwrap(), // Tuesday
+            None,
+        ),
+        Record::new(
+            "task3",
+            2700000,
+            NaiveDate::from_ymd_opt(2024, 1, 17).unwrap(), // Wednesday
+            None,
+        ),
     ];
 
     let today = NaiveDate::from_ymd_opt(2024, 1, 17).unwrap(); // Wednesday
@@ -243,24 +249,24 @@ fn test_period_filtering() {
 #[test]
 fn test_project_filtering() {
     let records = [
-        Record {
-            task: "task1".to_string(),
-            duration_ms: 3600000,
-            date: NaiveDate::from_ymd_opt(2024, 1, 15).unwrap(),
-            project: Some("project1".to_string()),
-        },
-        Record {
-            task: "task2".to_string(),
-            duration_ms: 1800000,
-            date: NaiveDate::from_ymd_opt(2024, 1, 16).unwrap(),
-            project: Some("project2".to_string()),
-        },
-        Record {
-            task: "task3".to_string(),
-            duration_ms: 2700000,
-            date: NaiveDate::from_ymd_opt(2024, 1, 17).unwrap(),
-            project: None,
-        },
+        Record::new(
+            "task1",
+            3600000,
+            NaiveDate::from_ymd_opt(2024, 1, 15).unwrap(),
+            Some("project1".to_string()),
+        ),
+        Record::new(
+            "task2",
+            1800000,
+            NaiveDate::from_ymd_opt(2024, 1, 16).unwrap(),
+            Some("project2".to_string()),
+        ),
+        Record::new(
+            "task3",
+            2700000,
+            NaiveDate::from_ymd_opt(2024, 1, 17).unwrap(),
+            None,
+        ),
     ];
 
     // Filter by project1
@@ -288,12 +294,12 @@ fn test_project_filtering() {
 #[test]
 #[serial]
 fn test_plugin_input_serialization() {
-    let records = vec![Record {
-        task: "task1".to_string(),
-        duration_ms: 3600000,
-        date: NaiveDate::from_ymd_opt(2024, 1, 15).unwrap(),
-        project: Some("project1".to_string()),
-    }];
+    let records = vec![Record::new(
+        "task1",
+        3600000,
+        NaiveDate::from_ymd_opt(2024, 1, 15).unwrap(),
+        Some("project1".to_string()),
+    )];
 
     let config = serde_json::json!({
         "api_key": "test_key",
@@ -301,6 +307,7 @@ fn test_plugin_input_serialization() {
     });
 
     let input = PluginInput {
+        protocol_version: PLUGIN_PROTOCOL_VERSION,
         records,
         period: "today".to_string(),
         config,
@@ -325,7 +332,7 @@ fn test_error_handling() {
     unsafe {
         env::set_var("TIMELOG_STATE_PATH", "/nonexistent/path/state.json");
     }
-    assert!(load_state().is_err());
+    assert!(load_state(DEFAULT_TIMER).is_err());
 
     // Test loading records when file doesn't exist
     unsafe {
@@ -363,12 +370,7 @@ fn test_duration_edge_cases() {
 #[test]
 fn test_date_formatting() {
     let date = NaiveDate::from_ymd_opt(2024, 1, 15).unwrap();
-    let record = Record {
-        task: "test task".to_string(),
-        duration_ms: 3600000,
-        date,
-        project: None,
-    };
+    let record = Record::new("test task", 3600000, date, None);
 
     // Test formatting for different periods
     let formatted_today = fmt_record_for_period(&record, Period::Today, date);
@@ -380,3 +382,822 @@ fn test_date_formatting() {
     let formatted_ytd = fmt_record_for_period(&record, Period::YTD, date);
     assert!(formatted_ytd.contains("2024-01-15"));
 }
+
+#[test]
+#[serial]
+fn test_load_config() {
+    let temp_dir = setup_test_env();
+
+    // Missing file falls back to defaults
+    assert_eq!(load_config().unwrap(), Config::default());
+
+    fs::write(
+        temp_dir.path().join("config.toml"),
+        "fiscal_year_start_month = 7\nweekend = [\"Fri\", \"Sat\"]\n",
+    )
+    .unwrap();
+    let config = load_config().unwrap();
+    assert_eq!(config.fiscal_year_start_month, Some(7));
+    assert_eq!(
+        config.weekend_days(),
+        vec![chrono::Weekday::Fri, chrono::Weekday::Sat]
+    );
+
+    fs::write(
+        temp_dir.path().join("config.toml"),
+        "default_project = \"acme\"\ndefault_period = \"last-week\"\nweek_start = \"Sun\"\ncolor = \"never\"\n",
+    )
+    .unwrap();
+    let config = load_config().unwrap();
+    assert_eq!(config.default_project, Some("acme".to_string()));
+    assert_eq!(config.default_period, Some(Period::LastWeek));
+    assert_eq!(config.week_start_day(), chrono::Weekday::Sun);
+    assert_eq!(config.color, Some(ColorChoice::Never));
+
+    fs::write(
+        temp_dir.path().join("config.toml"),
+        "workday_start = \"05:00\"\n",
+    )
+    .unwrap();
+    assert_eq!(
+        load_config().unwrap().day_start(),
+        chrono::NaiveTime::from_hms_opt(5, 0, 0).unwrap()
+    );
+    assert!(validate_config_files(&config_path().unwrap(), &plugin_dir().unwrap()).is_empty());
+
+    fs::write(
+        temp_dir.path().join("config.toml"),
+        "[report]\nformat = \"csv\"\n",
+    )
+    .unwrap();
+    assert_eq!(
+        load_config().unwrap().report.format,
+        Some(ReportFormat::Csv)
+    );
+
+    fs::write(
+        temp_dir.path().join("config.toml"),
+        "[billing]\ncurrency = \"$\"\ndefault_rate = 80.0\n\n[billing.rates]\nacme = 120.0\n",
+    )
+    .unwrap();
+    let billing = load_config().unwrap().billing;
+    assert_eq!(billing.rate_for(Some("acme")), Some(120.0));
+    assert_eq!(billing.rate_for(Some("other")), Some(80.0));
+    assert_eq!(billing.rate_for(None), Some(80.0));
+    assert_eq!(
+        billing.fmt_amount(billable_amount(5_400_000, 120.0)),
+        "$180.00"
+    );
+
+    // Sunday-start weeks: Wednesday 2024-06-05 falls in the week of Sunday 2024-06-02
+    let today = NaiveDate::from_ymd_opt(2024, 6, 5).unwrap();
+    assert_eq!(
+        period_range_for(Period::ThisWeek, today, &config),
+        (NaiveDate::from_ymd_opt(2024, 6, 2).unwrap(), today)
+    );
+
+    fs::write(
+        temp_dir.path().join("config.toml"),
+        "fiscal_year_start_month = 13\n",
+    )
+    .unwrap();
+    assert!(load_config().is_err());
+
+    cleanup_test_env();
+}
+
+#[test]
+fn test_last_record_index() {
+    let record = |task: &str, day: u32| {
+        Record::new(
+            task,
+            60000,
+            NaiveDate::from_ymd_opt(2024, 1, day).unwrap(),
+            None,
+        )
+    };
+
+    assert_eq!(last_record_index(&[]), None);
+
+    // Latest date wins even when it is not last in the file
+    let records = vec![record("a", 15), record("b", 16), record("c", 14)];
+    assert_eq!(last_record_index(&records), Some(1));
+
+    // Among records on the same date, the last one in file order wins
+    let records = vec![record("a", 16), record("b", 16), record("c", 14)];
+    assert_eq!(last_record_index(&records), Some(1));
+}
+
+#[test]
+#[serial]
+fn test_validate_config_files() {
+    let temp_dir = setup_test_env();
+    let config_file = temp_dir.path().join("config.toml");
+
+    // Nothing configured is a valid setup
+    assert!(validate_config_files(&config_file, &plugin_dir().unwrap()).is_empty());
+
+    fs::write(
+        &config_file,
+        "fiscal_year_start_month = 13\nweekend = [\"Caturday\"]\nfavourite_colour = \"blue\"\n",
+    )
+    .unwrap();
+    fs::write(
+        plugin_dir().unwrap().join("timelog-good.json"),
+        "{\"a\": 1}",
+    )
+    .unwrap();
+    fs::write(plugin_dir().unwrap().join("timelog-bad.json"), "{\"a\": ").unwrap();
+
+    let problems = validate_config_files(&config_file, &plugin_dir().unwrap());
+    let keyed: Vec<(&str, Option<&str>)> = problems
+        .iter()
+        .map(|p| {
+            (
+                p.file.file_name().unwrap().to_str().unwrap(),
+                p.key.as_deref(),
+            )
+        })
+        .collect();
+    assert_eq!(problems.len(), 4, "{problems:?}");
+    assert!(keyed.contains(&("config.toml", Some("fiscal_year_start_month"))));
+    assert!(keyed.contains(&("config.toml", Some("weekend"))));
+    assert!(keyed.contains(&("config.toml", Some("favourite_colour"))));
+    assert!(keyed.contains(&("timelog-bad.json", None)));
+
+    cleanup_test_env();
+}
+
+#[test]
+#[serial]
+fn test_event_log_round_trip() {
+    let _temp_dir = setup_test_env();
+
+    assert!(load_events().is_err());
+
+    let now = Utc::now();
+    let state = State::started("evented", Some("p".to_string()), now);
+    save_event(&Event::new(EventKind::Start, &state, now)).unwrap();
+    save_event(&Event::new(EventKind::Stop, &state, now)).unwrap();
+
+    let events = load_events().expect("Failed to load events");
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].kind, EventKind::Start);
+    assert_eq!(events[1].kind, EventKind::Stop);
+    assert_eq!(events[1].task, "evented");
+    assert_eq!(events[1].project, Some("p".to_string()));
+
+    cleanup_test_env();
+}
+
+#[test]
+#[serial]
+fn test_concurrent_record_writers() {
+    let temp_dir = setup_test_env();
+
+    let writers: Vec<_> = (0..2)
+        .map(|w| {
+            std::thread::spawn(move || {
+                for i in 0..50 {
+                    let record = Record::new(
+                        &format!("writer{w} task{i}"),
+                        1000,
+                        NaiveDate::from_ymd_opt(2024, 1, 15).unwrap(),
+                        Some("p".to_string()),
+                    );
+                    save_record(&record).expect("Failed to save record");
+                }
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+
+    // one header, every row intact
+    let contents = fs::read_to_string(record_path().unwrap()).unwrap();
+    assert_eq!(contents.matches("task,duration_ms").count(), 1);
+    let records = load_records().expect("record file should stay valid CSV");
+    assert_eq!(records.len(), 100);
+
+    // the lock is released once the writers are done, and its file removed
+    assert!(lock_file(&record_path().unwrap()).is_ok());
+    assert!(!temp_dir.path().join("records.csv.lock").exists());
+
+    cleanup_test_env();
+}
+
+#[test]
+#[serial]
+fn test_store_lock_holds_off_other_writers() {
+    check_store_lock_holds_off_other_writers("records.csv");
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+#[serial]
+fn test_sqlite_store_lock_holds_off_other_writers() {
+    check_store_lock_holds_off_other_writers("records.db");
+}
+
+/// A writer in another thread waits out a load and rewrite under the store lock of
+/// the record store at `record_file`, instead of being overwritten by it.
+fn check_store_lock_holds_off_other_writers(record_file: &str) {
+    let temp_dir = setup_test_env();
+    unsafe {
+        env::set_var("TIMELOG_RECORD_PATH", temp_dir.path().join(record_file));
+    }
+    let store = record_store().unwrap();
+    let record = |task: &str| {
+        Record::new(
+            task,
+            1000,
+            NaiveDate::from_ymd_opt(2024, 1, 15).unwrap(),
+            None,
+        )
+    };
+    store.append(&record("first")).unwrap();
+
+    let lock = store.lock().unwrap();
+    let writer = std::thread::spawn(move || save_record(&record("second")));
+    // the holder still writes; the other writer waits for the rewrite
+    let mut records = store.load_strict().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    records[0].task = "amended".to_string();
+    store.rewrite(&records).unwrap();
+    drop(lock);
+    writer.join().unwrap().unwrap();
+
+    let tasks: Vec<_> = load_records()
+        .unwrap()
+        .into_iter()
+        .map(|r| r.task)
+        .collect();
+    assert_eq!(tasks, vec!["amended", "second"]);
+    assert!(!temp_dir.path().join(format!("{record_file}.lock")).exists());
+
+    cleanup_test_env();
+}
+
+#[test]
+#[serial]
+fn test_interrupted_write_keeps_previous_state() {
+    let _temp_dir = setup_test_env();
+
+    let good = State {
+        timestamp: Utc::now(),
+        task: "good task".to_string(),
+        active: true,
+        project: None,
+        note: None,
+        tags: Vec::new(),
+    };
+    save_state(DEFAULT_TIMER, &good).unwrap();
+
+    // a write that dies halfway through
+    use std::io::Write;
+    let result = write_atomic(&state_path().unwrap(), |mut f| {
+        f.write_all(b"{\"timestamp\": ").unwrap();
+        Err("simulated crash".to_string())
+    });
+    assert_eq!(result, Err("simulated crash".to_string()));
+
+    assert_eq!(load_state(DEFAULT_TIMER).unwrap(), good);
+    let leftovers: Vec<_> = fs::read_dir(state_path().unwrap().parent().unwrap())
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name().to_string_lossy().ends_with(".tmp"))
+        .collect();
+    assert!(leftovers.is_empty());
+
+    cleanup_test_env();
+}
+
+#[test]
+#[serial]
+fn test_record_notes_round_trip() {
+    let temp_dir = setup_test_env();
+
+    let record_file = temp_dir.path().join("notes.csv");
+    fs::write(
+        &record_file,
+        "task,duration_ms,date,project,note\na,1000,2024-01-15,p,PR #42\nb,2000,2024-01-15,,\nc,3000,2024-01-15,p\n",
+    )
+    .unwrap();
+
+    let records = load_records_from_path(&record_file).unwrap();
+    assert_eq!(records[0].note, Some("PR #42".to_string()));
+    assert_eq!(records[1].note, None);
+    // a row written before notes existed
+    assert_eq!(records[2].note, None);
+    assert_eq!(records[2].project, Some("p".to_string()));
+
+    cleanup_test_env();
+}
+
+#[test]
+#[serial]
+fn test_record_tags_round_trip() {
+    let _temp_dir = setup_test_env();
+
+    let tagged = Record {
+        tags: vec!["meeting".to_string(), "team".to_string()],
+        ..Record::new(
+            "standup",
+            900000,
+            NaiveDate::from_ymd_opt(2024, 1, 15).unwrap(),
+            None,
+        )
+    };
+    let untagged = Record {
+        task: "coding".to_string(),
+        tags: Vec::new(),
+        ..tagged.clone()
+    };
+    save_record(&tagged).unwrap();
+    save_record(&untagged).unwrap();
+
+    let loaded = load_records().unwrap();
+    assert_eq!(loaded, vec![tagged, untagged]);
+
+    cleanup_test_env();
+}
+
+#[test]
+#[serial]
+fn test_record_timestamps_round_trip() {
+    let temp_dir = setup_test_env();
+
+    let now = chrono::DateTime::from_timestamp(1_705_320_000, 0).unwrap();
+    let state = State::started("deep work", None, now - chrono::Duration::minutes(90));
+    let stopped = state.to_record(now);
+    assert_eq!(stopped.end - stopped.start, chrono::Duration::minutes(90));
+    assert_eq!(stopped.duration_ms, 90 * 60 * 1000);
+
+    save_record(&stopped).unwrap();
+    assert_eq!(load_records().unwrap(), vec![stopped]);
+
+    // rows written before timestamps existed get a span synthesized from date and duration
+    let record_file = temp_dir.path().join("old.csv");
+    fs::write(
+        &record_file,
+        "task,duration_ms,date,project,note,tags\na,3600000,2024-01-15,,,\n",
+    )
+    .unwrap();
+    let old = load_records_from_path(&record_file).unwrap();
+    let date = NaiveDate::from_ymd_opt(2024, 1, 15).unwrap();
+    assert_eq!(
+        (old[0].start, old[0].end),
+        synthesized_span(date, 3_600_000)
+    );
+    assert_eq!(old[0].end - old[0].start, chrono::Duration::hours(1));
+
+    cleanup_test_env();
+}
+
+#[test]
+#[serial]
+fn test_corrupt_rows_are_skipped() {
+    let temp_dir = setup_test_env();
+
+    let record_file = temp_dir.path().join("corrupt.csv");
+    fs::write(
+        &record_file,
+        "task,duration_ms,date,project\n\
+         good,1000,2024-01-15,p\n\
+         bad duration,soon,2024-01-15,p\n\
+         bad date,1000,yesterday,p\n\
+         also good,2000,2024-01-16,\n",
+    )
+    .unwrap();
+
+    let (records, skipped) = read_records_from_path(&record_file).unwrap();
+    let tasks: Vec<&str> = records.iter().map(|r| r.task.as_str()).collect();
+    assert_eq!(tasks, vec!["good", "also good"]);
+    assert_eq!(
+        skipped,
+        vec![
+            SkippedRow {
+                line: 3,
+                error: "Invalid duration".to_string()
+            },
+            SkippedRow {
+                line: 4,
+                error: "Invalid date".to_string()
+            },
+        ]
+    );
+
+    // the default, lenient load keeps the good rows
+    assert_eq!(load_records_from_path(&record_file).unwrap(), records);
+
+    cleanup_test_env();
+}
+
+#[test]
+fn test_export_split_by_project() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let day = NaiveDate::from_ymd_opt(2024, 1, 15).unwrap();
+    let rows = vec![
+        Record::new("design", 3_600_000, day, Some("acme".to_string())),
+        Record::new("call", 1_800_000, day, Some("beta".to_string())),
+        Record::new("review", 900_000, day, Some("acme".to_string())),
+        Record::new("admin", 600_000, day, Some("none".to_string())),
+        Record::new("email", 300_000, day, None),
+    ];
+
+    let out_dir = temp_dir.path().join("invoices");
+    let written = export_split(&split_by_project(&rows), &out_dir).unwrap();
+    assert_eq!(
+        written,
+        vec![
+            out_dir.join("_no-project.csv"),
+            out_dir.join("acme.csv"),
+            out_dir.join("beta.csv"),
+            out_dir.join("none.csv"),
+        ]
+    );
+
+    let acme = load_records_from_path(&out_dir.join("acme.csv")).unwrap();
+    assert_eq!(acme, vec![rows[0].clone(), rows[2].clone()]);
+    let beta = load_records_from_path(&out_dir.join("beta.csv")).unwrap();
+    assert_eq!(beta, vec![rows[1].clone()]);
+    // a project called "none" doesn't swallow the records without one
+    let none = load_records_from_path(&out_dir.join("none.csv")).unwrap();
+    assert_eq!(none, vec![rows[3].clone()]);
+    let unassigned = load_records_from_path(&out_dir.join("_no-project.csv")).unwrap();
+    assert_eq!(unassigned, vec![rows[4].clone()]);
+}
+
+#[test]
+fn test_export_iso_duration_imports_back() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let day = NaiveDate::from_ymd_opt(2024, 1, 15).unwrap();
+    let rows = vec![
+        Record::new("design", 5_400_000, day, Some("acme".to_string())),
+        Record::new("call", 1_500, day, None),
+    ];
+
+    let path = temp_dir.path().join("iso.csv");
+    write_records_iso_duration_csv(&rows, fs::File::create(&path).unwrap()).unwrap();
+    let contents = fs::read_to_string(&path).unwrap();
+    assert!(contents.contains("design,PT1H30M,2024-01-15,acme"));
+    assert!(contents.contains("call,PT1.5S,2024-01-15,"));
+
+    let (imported, skipped) = read_import_file(&path).unwrap();
+    assert!(skipped.is_empty());
+    assert_eq!(imported, rows);
+}
+
+#[test]
+#[serial]
+fn test_storage_location() {
+    let temp_dir = setup_test_env();
+    let csv = temp_dir.path().join("records.csv");
+    let mut config = Config::default();
+    assert_eq!(
+        storage_location(&config).unwrap(),
+        (Storage::Csv, csv.clone())
+    );
+
+    config.storage = Some(Storage::Sqlite);
+    assert_eq!(
+        storage_location(&config).unwrap(),
+        (Storage::Sqlite, temp_dir.path().join("records.csv.db"))
+    );
+
+    let db = temp_dir.path().join("timelog.db");
+    unsafe {
+        env::set_var("TIMELOG_RECORD_PATH", &db);
+    }
+    assert_eq!(
+        storage_location(&Config::default()).unwrap(),
+        (Storage::Sqlite, db)
+    );
+
+    cleanup_test_env();
+}
+
+#[test]
+#[serial]
+fn test_record_store_survives_an_invalid_config() {
+    let temp_dir = setup_test_env();
+    let config_file = temp_dir.path().join("config.toml");
+    let day = NaiveDate::from_ymd_opt(2024, 1, 15).unwrap();
+
+    // a mistake elsewhere in the config leaves the records usable
+    fs::write(&config_file, "fiscal_year_start_month = 13\n").unwrap();
+    let store = record_store().unwrap();
+    store
+        .append(&Record::new("design", 1000, day, None))
+        .unwrap();
+    assert_eq!(record_store().unwrap().load().unwrap().len(), 1);
+    fs::write(&config_file, "fiscal_year_start_month = \n").unwrap();
+    assert_eq!(record_store().unwrap().load().unwrap().len(), 1);
+
+    // but not a mistake in the storage key itself
+    fs::write(&config_file, "storage = \"parquet\"\nweekend = 1\n").unwrap();
+    assert!(record_store().is_err());
+
+    cleanup_test_env();
+}
+
+#[cfg(not(feature = "sqlite"))]
+#[test]
+fn test_sqlite_store_needs_feature() {
+    let err = open_store(Storage::Sqlite, "records.db".into())
+        .err()
+        .expect("sqlite store without the feature");
+    assert!(err.contains("without the `sqlite` feature"), "{err}");
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_sqlite_store_round_trip() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let store = open_store(Storage::Sqlite, temp_dir.path().join("records.db")).unwrap();
+    assert!(!store.exists());
+    assert!(store.load().unwrap().is_empty());
+
+    let day = NaiveDate::from_ymd_opt(2024, 1, 15).unwrap();
+    let mut design = Record::new("design", 3_600_000, day, Some("acme".to_string()));
+    design.tags = vec!["client".to_string(), "deep".to_string()];
+    design.note = Some("wireframes".to_string());
+    let call = Record::new("call", 1_800_000, day, None);
+
+    store.append(&design).unwrap();
+    store.append_all(std::slice::from_ref(&call)).unwrap();
+    assert!(store.exists());
+    assert_eq!(store.load().unwrap(), vec![design.clone(), call]);
+
+    store.rewrite(std::slice::from_ref(&design)).unwrap();
+    assert_eq!(store.load().unwrap(), vec![design]);
+}
+
+#[test]
+fn test_csv_store_rewrite_keeps_unknown_columns() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("records.csv");
+    fs::write(
+        &path,
+        "task,duration_ms,date,project,note,tags,start,end,invoice\n\
+         design,3600000,2024-01-15,acme,,,2024-01-15T09:00:00.000Z,2024-01-15T10:00:00.000Z,INV-7\n\
+         review,1800000,2024-01-15,,,,2024-01-15T10:00:00.000Z,2024-01-15T10:30:00.000Z,\n",
+    )
+    .unwrap();
+
+    let store = CsvStore {
+        path: path.clone(),
+        backups: 0,
+    };
+    let mut records = store.load_strict().unwrap();
+    assert_eq!(records[0].extra, vec!["INV-7".to_string()]);
+    records[1].task = "code review".to_string();
+    store.rewrite(&records).unwrap();
+
+    let contents = fs::read_to_string(&path).unwrap();
+    let mut lines = contents.lines();
+    assert_eq!(
+        lines.next(),
+        Some("task,duration_ms,date,project,note,tags,start,end,invoice")
+    );
+    assert!(lines.next().unwrap().ends_with(",INV-7"));
+    assert!(lines.next().unwrap().starts_with("code review,"));
+    assert_eq!(store.load().unwrap(), records);
+}
+
+#[test]
+fn test_backup_record_file_prunes_old_backups() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("records.csv");
+    let at = |h, m, s| {
+        chrono::Local
+            .with_ymd_and_hms(2024, 1, 15, h, m, s)
+            .single()
+            .unwrap()
+    };
+
+    for (i, min) in [1, 2, 3].into_iter().enumerate() {
+        fs::write(&path, format!("version {i}\n")).unwrap();
+        backup_record_file(&path, 2, at(9, min, 0)).unwrap();
+    }
+    // a second backup in the same second doesn't overwrite the first
+    fs::write(&path, "version 3\n").unwrap();
+    let same_second = backup_record_file(&path, 2, at(9, 3, 0)).unwrap();
+    assert!(same_second.ends_with("records.csv.bak-20240115090300-2"));
+
+    let kept = record_backups(&path);
+    let names: Vec<_> = kept
+        .iter()
+        .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            "records.csv.bak-20240115090300",
+            "records.csv.bak-20240115090300-2"
+        ]
+    );
+    assert_eq!(fs::read_to_string(&kept[0]).unwrap(), "version 2\n");
+    assert_eq!(fs::read_to_string(&kept[1]).unwrap(), "version 3\n");
+}
+
+#[test]
+#[serial]
+fn test_doctor_checks() {
+    let temp_dir = setup_test_env();
+    let dir = temp_dir.path();
+    fs::write(
+        dir.join("records.csv"),
+        "task,duration_ms,date,project,note,tags,start,end\n\
+         design,3600000,2024-01-15,acme,,,2024-01-15T09:00:00.000Z,2024-01-15T10:00:00.000Z\n\
+         review,soon,2024-01-15,,,,,\n",
+    )
+    .unwrap();
+    fs::write(dir.join("state.json"), "{\"task\": ").unwrap();
+
+    let checks = doctor_checks();
+    let find = |name: &str| checks.iter().find(|c| c.name == name).unwrap();
+    assert_eq!(find("HOME").status, CheckStatus::Pass);
+    assert_eq!(find("record file").status, CheckStatus::Pass);
+    let records = find("records");
+    assert_eq!(records.status, CheckStatus::Fail);
+    assert_eq!(records.details.len(), 1);
+    assert!(records.details[0].starts_with("line 3:"), "{records:?}");
+    assert_eq!(find("state").status, CheckStatus::Fail);
+
+    fs::remove_file(dir.join("state.json")).unwrap();
+    assert_eq!(
+        check_state_file(&dir.join("state.json")).status,
+        CheckStatus::Pass
+    );
+
+    cleanup_test_env();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_doctor_warns_about_plugins_that_are_not_executable() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let dir = temp_dir.path();
+    fs::write(dir.join("timelog-jira"), "#!/bin/sh\n").unwrap();
+    fs::set_permissions(dir.join("timelog-jira"), fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(dir.join("timelog-toggl"), "#!/bin/sh\n").unwrap();
+    fs::write(dir.join("timelog-toggl.json"), "{}").unwrap();
+
+    let checks = check_plugins(dir);
+    let statuses: Vec<_> = checks.iter().map(|c| (c.name.as_str(), c.status)).collect();
+    assert_eq!(
+        statuses,
+        vec![
+            ("plugin jira", CheckStatus::Pass),
+            ("plugin toggl", CheckStatus::Warn)
+        ]
+    );
+}
+
+#[test]
+#[serial]
+fn test_paths_without_home_are_errors() {
+    let temp_dir = setup_test_env();
+    let home = env::var_os("HOME");
+    unsafe {
+        env::remove_var("HOME");
+        env::remove_var("USERPROFILE");
+        env::remove_var("TIMELOG_RECORD_PATH");
+    }
+
+    let err = record_path().unwrap_err();
+    assert!(err.contains("not set"), "{err}");
+    assert!(err.contains("TIMELOG_RECORD_PATH"), "{err}");
+    assert!(storage_location(&Config::default()).is_err());
+    assert!(save_record(&Record::new("design", 1000, Utc::now().date_naive(), None)).is_err());
+    // paths given explicitly still resolve
+    assert_eq!(state_path().unwrap(), temp_dir.path().join("state.json"));
+
+    unsafe {
+        if let Some(home) = home {
+            env::set_var("HOME", home);
+        }
+    }
+    cleanup_test_env();
+}
+
+#[test]
+#[serial]
+fn test_legacy_state_file_becomes_the_default_timer() {
+    let temp_dir = setup_test_env();
+    let state_file = temp_dir.path().join("state.json");
+    fs::write(
+        &state_file,
+        r#"{"timestamp":"2024-01-15T09:00:00Z","task":"legacy","active":true,"project":null}"#,
+    )
+    .unwrap();
+
+    let legacy = load_state(DEFAULT_TIMER).unwrap();
+    assert_eq!(legacy.task, "legacy");
+    assert!(state_present(DEFAULT_TIMER).unwrap());
+    assert!(!state_present("build").unwrap());
+
+    // the next write keeps the legacy task and switches to the named layout
+    let build = State::started("compile", None, Utc::now());
+    save_state("build", &build).unwrap();
+    let contents = fs::read_to_string(&state_file).unwrap();
+    assert!(contents.contains("\"timers\""), "{contents}");
+    let timers = load_timers().unwrap();
+    assert_eq!(timers.len(), 2);
+    assert_eq!(timers[DEFAULT_TIMER], legacy);
+    assert_eq!(timers["build"], build);
+
+    delete_state(DEFAULT_TIMER).unwrap();
+    assert_eq!(
+        load_state(DEFAULT_TIMER),
+        Err(StateError::NoTimer(DEFAULT_TIMER.to_string()))
+    );
+    assert_eq!(load_state("build").unwrap(), build);
+    delete_state("build").unwrap();
+    assert!(!state_file.exists());
+
+    cleanup_test_env();
+}
+
+#[test]
+#[serial]
+fn test_push_pop_round_trip() {
+    let _temp_dir = setup_test_env();
+    let t0 = Utc::now() - chrono::Duration::hours(1);
+    let minutes = |m: i64| chrono::Duration::minutes(m);
+
+    let feature = State::started("feature", Some("acme".to_string()), t0);
+    assert!(push_state(&feature, t0).is_err());
+    assert!(pop_state(t0).is_err());
+    save_state(DEFAULT_TIMER, &feature).unwrap();
+
+    // two interruptions, one on top of the other
+    let call = State::started("call", None, t0 + minutes(20));
+    push_state(&call, t0 + minutes(20)).unwrap();
+    let incident = State::started("incident", None, t0 + minutes(25));
+    assert_eq!(push_state(&incident, t0 + minutes(25)).unwrap(), call);
+    let stack = load_stack().unwrap();
+    assert_eq!(
+        stack.iter().map(|s| s.task.as_str()).collect::<Vec<_>>(),
+        vec!["feature", "call"]
+    );
+    assert!(stack.iter().all(|s| !s.active));
+    assert_eq!(load_state(DEFAULT_TIMER).unwrap(), incident);
+
+    let now = t0 + minutes(30);
+    let (replaced, call_again) = pop_state(now).unwrap();
+    assert_eq!(replaced, Some(incident));
+    assert_eq!(call_again.task, "call");
+    assert!(call_again.active);
+    assert_eq!(call_again.elapsed_ms(now), minutes(5).num_milliseconds());
+
+    let now = t0 + minutes(40);
+    // a pop whose replaced timer couldn't be recorded is undone
+    let (replaced, feature_again) = pop_state(now).unwrap();
+    unpop_state(replaced.as_ref(), &feature_again, now).unwrap();
+    assert_eq!(load_state(DEFAULT_TIMER).unwrap(), call_again);
+    assert_eq!(load_stack().unwrap().len(), 1);
+
+    let (replaced, feature_again) = pop_state(now).unwrap();
+    assert_eq!(replaced.map(|s| s.task), Some("call".to_string()));
+    assert_eq!(feature_again.task, "feature");
+    assert_eq!(feature_again.project.as_deref(), Some("acme"));
+    assert_eq!(
+        feature_again.elapsed_ms(now),
+        minutes(20).num_milliseconds()
+    );
+    assert!(load_stack().unwrap().is_empty());
+    assert_eq!(
+        pop_state(now).unwrap_err(),
+        "the task stack is empty; nothing to pop"
+    );
+
+    cleanup_test_env();
+}
+
+#[test]
+#[serial]
+fn test_truncated_state_file_is_reported_as_corrupt() {
+    let temp_dir = setup_test_env();
+    let state_file = temp_dir.path().join("state.json");
+    fs::write(&state_file, "{ \"timestamp\": ").unwrap();
+
+    let err = load_state(DEFAULT_TIMER).unwrap_err();
+    assert!(matches!(&err, StateError::Corrupt { path, .. } if *path == state_file));
+    assert!(err.to_string().starts_with("state file is corrupt"));
+    assert!(err.to_string().ends_with("run `timelog cancel` to reset"));
+    assert!(matches!(
+        state_present(DEFAULT_TIMER),
+        Err(StateError::Corrupt { .. })
+    ));
+    assert_eq!(read_state_if_present(DEFAULT_TIMER), None);
+
+    remove_state_file().unwrap();
+    assert!(load_timers().unwrap().is_empty());
+
+    cleanup_test_env();
+}
@@ -2,6 +2,7 @@ use serial_test::serial;
 use std::env;
 use std::fs;
 use std::os::unix::fs::PermissionsExt;
+use std::time::Duration;
 use tempfile::TempDir;
 
 use timelog::*;
@@ -16,6 +17,8 @@ fn setup_plugin_test_env() -> TempDir {
         env::set_var("TIMELOG_RECORD_PATH", format!("{temp_path}/records.csv"));
         env::set_var("TIMELOG_STATE_PATH", format!("{temp_path}/state.json"));
         env::set_var("TIMELOG_PLUGIN_PATH", format!("{temp_path}/plugins"));
+        env::set_var("TIMELOG_CONFIG_PATH", format!("{temp_path}/config.toml"));
+        env::set_var("TIMELOG_EVENTS_PATH", format!("{temp_path}/events.jsonl"));
     }
 
     // Create plugins directory
@@ -30,6 +33,8 @@ fn cleanup_plugin_test_env() {
         env::remove_var("TIMELOG_RECORD_PATH");
         env::remove_var("TIMELOG_STATE_PATH");
         env::remove_var("TIMELOG_PLUGIN_PATH");
+        env::remove_var("TIMELOG_CONFIG_PATH");
+        env::remove_var("TIMELOG_EVENTS_PATH");
     }
 }
 
@@ -38,7 +43,7 @@ fn cleanup_plugin_test_env() {
 fn test_plugin_discovery_empty_directory() {
     let _temp_dir = setup_plugin_test_env();
 
-    let plugins = discover_plugins();
+    let plugins = discover_plugins().unwrap();
     assert_eq!(plugins.len(), 0);
 
     cleanup_plugin_test_env();
@@ -49,7 +54,7 @@ fn test_plugin_discovery_empty_directory() {
 fn test_plugin_discovery_with_plugins() {
     let _temp_dir = setup_plugin_test_env();
 
-    let plugin_dir = plugin_dir();
+    let plugin_dir = plugin_dir().unwrap();
 
     // Create valid plugins
     let plugin1_path = plugin_dir.join("timelog-test1");
@@ -67,7 +72,7 @@ fn test_plugin_discovery_with_plugins() {
     perms2.set_mode(0o755);
     fs::set_permissions(&plugin2_path, perms2).expect("Failed to set permissions");
 
-    let plugins = discover_plugins();
+    let plugins = discover_plugins().unwrap();
     assert_eq!(plugins.len(), 2);
     assert!(plugins.contains(&"test1".to_string()));
     assert!(plugins.contains(&"another".to_string()));
@@ -80,7 +85,7 @@ fn test_plugin_discovery_with_plugins() {
 fn test_plugin_discovery_ignores_non_executable() {
     let _temp_dir = setup_plugin_test_env();
 
-    let plugin_dir = plugin_dir();
+    let plugin_dir = plugin_dir().unwrap();
 
     // Create a non-executable file with timelog- prefix
     let non_exec_path = plugin_dir.join("timelog-notexec");
@@ -94,7 +99,7 @@ fn test_plugin_discovery_ignores_non_executable() {
     perms.set_mode(0o755);
     fs::set_permissions(&plugin_path, perms).expect("Failed to set permissions");
 
-    let plugins = discover_plugins();
+    let plugins = discover_plugins().unwrap();
     assert_eq!(plugins.len(), 1);
     assert!(plugins.contains(&"good".to_string()));
     assert!(!plugins.contains(&"notexec".to_string()));
@@ -107,7 +112,7 @@ fn test_plugin_discovery_ignores_non_executable() {
 fn test_plugin_discovery_ignores_config_files() {
     let _temp_dir = setup_plugin_test_env();
 
-    let plugin_dir = plugin_dir();
+    let plugin_dir = plugin_dir().unwrap();
 
     // Create plugin and its config file
     let plugin_path = plugin_dir.join("timelog-withconfig");
@@ -120,7 +125,7 @@ fn test_plugin_discovery_ignores_config_files() {
     perms.set_mode(0o755);
     fs::set_permissions(&plugin_path, perms).expect("Failed to set permissions");
 
-    let plugins = discover_plugins();
+    let plugins = discover_plugins().unwrap();
     assert_eq!(plugins.len(), 1);
     assert!(plugins.contains(&"withconfig".to_string()));
 
@@ -132,7 +137,7 @@ fn test_plugin_discovery_ignores_config_files() {
 fn test_plugin_discovery_ignores_non_timelog_files() {
     let _temp_dir = setup_plugin_test_env();
 
-    let plugin_dir = plugin_dir();
+    let plugin_dir = plugin_dir().unwrap();
 
     // Create files that don't start with "timelog-"
     let other_file = plugin_dir.join("other-script");
@@ -152,7 +157,7 @@ fn test_plugin_discovery_ignores_non_timelog_files() {
     perms.set_mode(0o755);
     fs::set_permissions(&plugin_path, perms).expect("Failed to set permissions");
 
-    let plugins = discover_plugins();
+    let plugins = discover_plugins().unwrap();
     assert_eq!(plugins.len(), 1);
     assert!(plugins.contains(&"valid".to_string()));
 
@@ -166,18 +171,18 @@ fn test_plugin_input_structure() {
 
     // Create test records
     let records = vec![
-        Record {
-            task: "task1".to_string(),
-            duration_ms: 3600000,
-            date: chrono::NaiveDate::from_ymd_opt(2024, 1, 15).unwrap(),
-            project: Some("project1".to_string()),
-        },
-        Record {
-            task: "task2".to_string(),
-            duration_ms: 1800000,
-            date: chrono::NaiveDate::from_ymd_opt(2024, 1, 15).unwrap(),
-            project: None,
-        },
+        Record::new(
+            "task1",
+            3600000,
+            chrono::NaiveDate::from_ymd_opt(2024, 1, 15).unwrap(),
+            Some("project1".to_string()),
+        ),
+        Record::new(
+            "task2",
+            1800000,
+            chrono::NaiveDate::from_ymd_opt(2024, 1, 15).unwrap(),
+            None,
+        ),
     ];
 
     let config = serde_json::json!({
@@ -186,6 +191,7 @@ fn test_plugin_input_structure() {
     });
 
     let input = PluginInput {
+        protocol_version: PLUGIN_PROTOCOL_VERSION,
         records,
         period: "today".to_string(),
         config,
@@ -204,6 +210,44 @@ fn test_plugin_input_structure() {
     assert_eq!(parsed["records"][1]["task"], "task2");
     assert!(parsed["records"][1]["project"].is_null());
     assert_eq!(parsed["config"]["api_key"], "test_key");
+    assert_eq!(parsed["protocol_version"], PLUGIN_PROTOCOL_VERSION);
+
+    cleanup_plugin_test_env();
+}
+
+#[test]
+#[serial]
+fn test_plugin_input_sends_records_in_a_stable_order() {
+    let _temp_dir = setup_plugin_test_env();
+
+    let day = |d: u32| chrono::NaiveDate::from_ymd_opt(2024, 1, d).unwrap();
+    let at = |d: u32, h: u32| day(d).and_hms_opt(h, 0, 0).unwrap().and_utc();
+    // shuffled: out of date order, out of start order within a day, and a legacy
+    // record whose span is synthesized from its date
+    let mut records = vec![
+        Record::spanning("late", at(16, 14), at(16, 15), None),
+        Record::new("legacy", 1800000, day(15), None),
+        Record::spanning("early", at(16, 9), at(16, 10), None),
+        Record::spanning("beta", at(15, 12), at(15, 13), None),
+        Record::spanning("alpha", at(15, 12), at(15, 13), None),
+    ];
+    sort_plugin_records(&mut records);
+
+    let input = PluginInput {
+        protocol_version: PLUGIN_PROTOCOL_VERSION,
+        records,
+        period: "this-week".to_string(),
+        config: serde_json::json!({}),
+    };
+    let parsed: serde_json::Value = serde_json::to_value(&input).unwrap();
+    let tasks: Vec<&str> = parsed["records"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["task"].as_str().unwrap())
+        .collect();
+
+    assert_eq!(tasks, ["legacy", "alpha", "beta", "early", "late"]);
 
     cleanup_plugin_test_env();
 }
@@ -213,7 +257,7 @@ fn test_plugin_input_structure() {
 fn test_plugin_execution_success() {
     let _temp_dir = setup_plugin_test_env();
 
-    let plugin_dir = plugin_dir();
+    let plugin_dir = plugin_dir().unwrap();
     let plugin_path = plugin_dir.join("timelog-test");
 
     // Create a test plugin that returns success
@@ -233,21 +277,22 @@ echo '{
     fs::set_permissions(&plugin_path, perms).expect("Failed to set permissions");
 
     // Create test input
-    let records = vec![Record {
-        task: "test".to_string(),
-        duration_ms: 1000,
-        date: chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
-        project: None,
-    }];
+    let records = vec![Record::new(
+        "test",
+        1000,
+        chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+        None,
+    )];
 
     let input = PluginInput {
+        protocol_version: PLUGIN_PROTOCOL_VERSION,
         records,
         period: "today".to_string(),
         config: serde_json::Value::Object(serde_json::Map::new()),
     };
 
     // Execute plugin
-    let result = execute_plugin("test", &input, false);
+    let result = execute_plugin("test", &input, false, DEFAULT_PLUGIN_TIMEOUT);
 
     assert!(result.is_ok());
     let output = result.unwrap();
@@ -264,7 +309,7 @@ echo '{
 fn test_plugin_execution_failure() {
     let _temp_dir = setup_plugin_test_env();
 
-    let plugin_dir = plugin_dir();
+    let plugin_dir = plugin_dir().unwrap();
     let plugin_path = plugin_dir.join("timelog-fail");
 
     // Create a test plugin that returns failure
@@ -286,13 +331,14 @@ exit 1
 
     let records = vec![];
     let input = PluginInput {
+        protocol_version: PLUGIN_PROTOCOL_VERSION,
         records,
         period: "today".to_string(),
         config: serde_json::Value::Object(serde_json::Map::new()),
     };
 
     // Execute plugin
-    let result = execute_plugin("fail", &input, false);
+    let result = execute_plugin("fail", &input, false, DEFAULT_PLUGIN_TIMEOUT);
 
     assert!(result.is_err());
     let error = result.unwrap_err();
@@ -306,7 +352,7 @@ exit 1
 fn test_plugin_execution_dry_run() {
     let _temp_dir = setup_plugin_test_env();
 
-    let plugin_dir = plugin_dir();
+    let plugin_dir = plugin_dir().unwrap();
     let plugin_path = plugin_dir.join("timelog-dryrun");
 
     // Create a test plugin that handles dry-run flag
@@ -336,13 +382,14 @@ fi
 
     let records = vec![];
     let input = PluginInput {
+        protocol_version: PLUGIN_PROTOCOL_VERSION,
         records,
         period: "today".to_string(),
         config: serde_json::Value::Object(serde_json::Map::new()),
     };
 
     // Test dry run
-    let result = execute_plugin("dryrun", &input, true);
+    let result = execute_plugin("dryrun", &input, true, DEFAULT_PLUGIN_TIMEOUT);
     assert!(result.is_ok());
     let output = result.unwrap();
     assert!(output.success);
@@ -350,7 +397,7 @@ fi
     assert_eq!(output.uploaded_count, Some(0));
 
     // Test actual run
-    let result = execute_plugin("dryrun", &input, false);
+    let result = execute_plugin("dryrun", &input, false, DEFAULT_PLUGIN_TIMEOUT);
     assert!(result.is_ok());
     let output = result.unwrap();
     assert!(output.success);
@@ -367,13 +414,14 @@ fn test_plugin_execution_nonexistent() {
 
     let records = vec![];
     let input = PluginInput {
+        protocol_version: PLUGIN_PROTOCOL_VERSION,
         records,
         period: "today".to_string(),
         config: serde_json::Value::Object(serde_json::Map::new()),
     };
 
     // Try to execute non-existent plugin
-    let result = execute_plugin("nonexistent", &input, false);
+    let result = execute_plugin("nonexistent", &input, false, DEFAULT_PLUGIN_TIMEOUT);
 
     assert!(result.is_err());
     let error = result.unwrap_err();
@@ -387,7 +435,7 @@ fn test_plugin_execution_nonexistent() {
 fn test_plugin_execution_invalid_json() {
     let _temp_dir = setup_plugin_test_env();
 
-    let plugin_dir = plugin_dir();
+    let plugin_dir = plugin_dir().unwrap();
     let plugin_path = plugin_dir.join("timelog-badjson");
 
     // Create a test plugin that returns invalid JSON
@@ -403,13 +451,14 @@ echo 'invalid json output'
 
     let records = vec![];
     let input = PluginInput {
+        protocol_version: PLUGIN_PROTOCOL_VERSION,
         records,
         period: "today".to_string(),
         config: serde_json::Value::Object(serde_json::Map::new()),
     };
 
     // Execute plugin
-    let result = execute_plugin("badjson", &input, false);
+    let result = execute_plugin("badjson", &input, false, DEFAULT_PLUGIN_TIMEOUT);
 
     assert!(result.is_err());
     let error = result.unwrap_err();
@@ -423,7 +472,7 @@ echo 'invalid json output'
 fn test_plugin_with_config() {
     let _temp_dir = setup_plugin_test_env();
 
-    let plugin_dir = plugin_dir();
+    let plugin_dir = plugin_dir().unwrap();
 
     // Create plugin config
     let config_path = plugin_dir.join("timelog-configured.json");
@@ -467,13 +516,14 @@ fi
 
     let records = vec![];
     let input = PluginInput {
+        protocol_version: PLUGIN_PROTOCOL_VERSION,
         records,
         period: "today".to_string(),
         config: config_content,
     };
 
     // Execute plugin
-    let result = execute_plugin("configured", &input, false);
+    let result = execute_plugin("configured", &input, false, DEFAULT_PLUGIN_TIMEOUT);
 
     assert!(result.is_ok());
     let output = result.unwrap();
@@ -482,3 +532,381 @@ fi
 
     cleanup_plugin_test_env();
 }
+
+#[test]
+#[serial]
+fn test_plugin_config_from_toml() {
+    let _temp_dir = setup_plugin_test_env();
+
+    let plugin_dir = plugin_dir().unwrap();
+    let plugin_path = plugin_dir.join("timelog-tomlcfg");
+
+    // Plugin that keeps a copy of its stdin so we can inspect what it received
+    let plugin_script = r#"#!/bin/bash
+cat > "$(dirname "$0")/received.json"
+echo '{"success": true, "message": "ok", "uploaded_count": 1, "errors": []}'
+"#;
+    fs::write(&plugin_path, plugin_script).expect("Failed to create plugin");
+    let mut perms = fs::metadata(&plugin_path).unwrap().permissions();
+    perms.set_mode(0o755);
+    fs::set_permissions(&plugin_path, perms).expect("Failed to set permissions");
+
+    fs::write(
+        plugin_dir.join("timelog-tomlcfg.toml"),
+        "api_key = \"secret\"\nretries = 3\n\n[task_issue_map]\ncoding = \"DEV-456\"\n",
+    )
+    .expect("Failed to create config");
+
+    let config = load_plugin_config("tomlcfg").expect("Failed to load TOML config");
+    assert_eq!(
+        config,
+        serde_json::json!({
+            "api_key": "secret",
+            "retries": 3,
+            "task_issue_map": { "coding": "DEV-456" }
+        })
+    );
+
+    let input = PluginInput {
+        protocol_version: PLUGIN_PROTOCOL_VERSION,
+        records: vec![Record::new(
+            "coding",
+            1000,
+            chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            None,
+        )],
+        period: "today".to_string(),
+        config: config.clone(),
+    };
+    execute_plugin("tomlcfg", &input, false, DEFAULT_PLUGIN_TIMEOUT)
+        .expect("Plugin execution failed");
+
+    let received: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(plugin_dir.join("received.json")).unwrap())
+            .unwrap();
+    assert_eq!(received["config"], config);
+
+    // JSON takes precedence when both exist
+    fs::write(
+        plugin_dir.join("timelog-tomlcfg.json"),
+        "{\"api_key\": \"json\"}",
+    )
+    .unwrap();
+    let config = load_plugin_config("tomlcfg").unwrap();
+    assert_eq!(config["api_key"], "json");
+
+    cleanup_plugin_test_env();
+}
+
+#[test]
+#[serial]
+fn test_plugin_install_and_remove() {
+    let temp_dir = setup_plugin_test_env();
+
+    let script = temp_dir.path().join("timelog-invoice");
+    fs::write(&script, "#!/bin/bash\necho '{}'").unwrap();
+
+    // the directory is created on demand and the copy made executable
+    fs::remove_dir_all(plugin_dir().unwrap()).unwrap();
+    let installed = install_plugin(&script, None, false).unwrap();
+    assert_eq!(installed, plugin_dir().unwrap().join("timelog-invoice"));
+    assert_ne!(
+        fs::metadata(&installed).unwrap().permissions().mode() & 0o111,
+        0
+    );
+    assert_eq!(discover_plugins().unwrap(), vec!["invoice".to_string()]);
+
+    let err = install_plugin(&script, None, false).unwrap_err();
+    assert!(err.contains("--force"));
+    assert!(install_plugin(&script, None, true).is_ok());
+
+    install_plugin(&script, Some("billing"), false).unwrap();
+    let mut plugins = discover_plugins().unwrap();
+    plugins.sort();
+    assert_eq!(plugins, vec!["billing".to_string(), "invoice".to_string()]);
+
+    assert!(install_plugin(&script, Some("../escape"), false).is_err());
+
+    remove_plugin("invoice").unwrap();
+    assert_eq!(discover_plugins().unwrap(), vec!["billing".to_string()]);
+    assert!(remove_plugin("invoice").is_err());
+
+    cleanup_plugin_test_env();
+}
+
+#[test]
+#[serial]
+fn test_plugin_execution_timeout() {
+    let _temp_dir = setup_plugin_test_env();
+
+    let plugin_path = plugin_dir().unwrap().join("timelog-hang");
+    fs::write(&plugin_path, "#!/bin/bash\ncat > /dev/null\nsleep 10\n").unwrap();
+    let mut perms = fs::metadata(&plugin_path).unwrap().permissions();
+    perms.set_mode(0o755);
+    fs::set_permissions(&plugin_path, perms).unwrap();
+
+    let input = PluginInput {
+        protocol_version: PLUGIN_PROTOCOL_VERSION,
+        records: vec![],
+        period: "today".to_string(),
+        config: serde_json::Value::Object(serde_json::Map::new()),
+    };
+
+    let started = std::time::Instant::now();
+    let err = execute_plugin("hang", &input, false, Duration::from_millis(300)).unwrap_err();
+    assert_eq!(err, "plugin 'hang' timed out after 300ms");
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    cleanup_plugin_test_env();
+}
+
+#[test]
+fn test_plugin_timeout_precedence() {
+    let config = serde_json::json!({ "timeout_secs": 5 });
+    assert_eq!(plugin_timeout(Some(2), &config), Duration::from_secs(2));
+    assert_eq!(plugin_timeout(None, &config), Duration::from_secs(5));
+    assert_eq!(
+        plugin_timeout(None, &serde_json::json!({})),
+        DEFAULT_PLUGIN_TIMEOUT
+    );
+}
+
+#[test]
+#[serial]
+fn test_plugin_environment() {
+    let _temp_dir = setup_plugin_test_env();
+
+    let plugin_dir = plugin_dir().unwrap();
+    let plugin_path = plugin_dir.join("timelog-envcheck");
+    // reports what it saw in its environment, without parsing stdin
+    let plugin_script = r#"#!/bin/bash
+cat > /dev/null
+echo "{\"success\": true, \"message\": \"$TIMELOG_PERIOD|$TIMELOG_PLUGIN_CONFIG\", \"uploaded_count\": null, \"errors\": []}"
+"#;
+    fs::write(&plugin_path, plugin_script).unwrap();
+    let mut perms = fs::metadata(&plugin_path).unwrap().permissions();
+    perms.set_mode(0o755);
+    fs::set_permissions(&plugin_path, perms).unwrap();
+
+    let input = PluginInput {
+        protocol_version: PLUGIN_PROTOCOL_VERSION,
+        records: vec![],
+        period: "last-week".to_string(),
+        config: serde_json::Value::Object(serde_json::Map::new()),
+    };
+
+    let output = execute_plugin("envcheck", &input, false, DEFAULT_PLUGIN_TIMEOUT).unwrap();
+    assert_eq!(output.message, "last-week|");
+
+    let config_path = plugin_dir.join("timelog-envcheck.toml");
+    fs::write(&config_path, "api_key = \"secret\"\n").unwrap();
+    let output = execute_plugin("envcheck", &input, false, DEFAULT_PLUGIN_TIMEOUT).unwrap();
+    assert_eq!(
+        output.message,
+        format!("last-week|{}", config_path.display())
+    );
+
+    cleanup_plugin_test_env();
+}
+
+#[test]
+#[serial]
+fn test_execute_plugins_runs_each_independently() {
+    let _temp_dir = setup_plugin_test_env();
+
+    let scripts = [
+        (
+            "slow",
+            "cat > /dev/null\nsleep 1\necho '{\"success\": true, \"message\": \"slow done\", \"uploaded_count\": 1, \"errors\": []}'",
+        ),
+        ("broken", "cat > /dev/null\necho 'boom' >&2\nexit 3"),
+        (
+            "fast",
+            "cat > /dev/null\necho '{\"success\": true, \"message\": \"fast done\", \"uploaded_count\": 1, \"errors\": []}'",
+        ),
+    ];
+    for (name, body) in scripts {
+        let plugin_path = plugin_dir().unwrap().join(format!("timelog-{name}"));
+        fs::write(&plugin_path, format!("#!/bin/bash\n{body}\n")).unwrap();
+        let mut perms = fs::metadata(&plugin_path).unwrap().permissions();
+        perms.set_mode(0o755);
+        fs::set_permissions(&plugin_path, perms).unwrap();
+    }
+
+    let jobs: Vec<PluginJob> = scripts
+        .iter()
+        .map(|(name, _)| PluginJob {
+            name: name.to_string(),
+            input: PluginInput {
+                protocol_version: PLUGIN_PROTOCOL_VERSION,
+                records: vec![Record::new(
+                    "test",
+                    1000,
+                    chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+                    None,
+                )],
+                period: "today".to_string(),
+                config: serde_json::Value::Object(serde_json::Map::new()),
+            },
+            timeout: DEFAULT_PLUGIN_TIMEOUT,
+        })
+        .collect();
+
+    let results = execute_plugins(&jobs, false);
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].as_ref().unwrap().message, "slow done");
+    assert!(results[1].as_ref().unwrap_err().contains("boom"));
+    assert_eq!(results[2].as_ref().unwrap().message, "fast done");
+
+    cleanup_plugin_test_env();
+}
+
+#[test]
+#[serial]
+fn test_plugin_output_inconsistencies() {
+    let _temp_dir = setup_plugin_test_env();
+
+    let shapes = [
+        (
+            "errors",
+            r#"{"success": true, "message": "done", "uploaded_count": 1, "errors": ["row 2 skipped"]}"#,
+            vec!["reported success along with 1 error"],
+        ),
+        (
+            "overcount",
+            r#"{"success": true, "message": "done", "uploaded_count": 5, "errors": []}"#,
+            vec!["reported 5 records uploaded but only 1 were sent"],
+        ),
+        (
+            "consistent",
+            r#"{"success": true, "message": "done", "uploaded_count": 1, "errors": []}"#,
+            vec![],
+        ),
+    ];
+
+    let input = PluginInput {
+        protocol_version: PLUGIN_PROTOCOL_VERSION,
+        records: vec![Record::new(
+            "test",
+            1000,
+            chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            None,
+        )],
+        period: "today".to_string(),
+        config: serde_json::Value::Object(serde_json::Map::new()),
+    };
+
+    for (name, output, expected) in shapes {
+        let plugin_path = plugin_dir().unwrap().join(format!("timelog-{name}"));
+        fs::write(
+            &plugin_path,
+            format!("#!/bin/bash\ncat > /dev/null\necho '{output}'\n"),
+        )
+        .unwrap();
+        let mut perms = fs::metadata(&plugin_path).unwrap().permissions();
+        perms.set_mode(0o755);
+        fs::set_permissions(&plugin_path, perms).unwrap();
+
+        let output = execute_plugin(name, &input, false, DEFAULT_PLUGIN_TIMEOUT).unwrap();
+        assert_eq!(
+            output.inconsistencies(input.records.len()),
+            expected,
+            "{name}"
+        );
+    }
+
+    let output = execute_plugin("errors", &input, false, DEFAULT_PLUGIN_TIMEOUT).unwrap();
+    assert!(output.succeeded(false));
+    assert!(!output.succeeded(true));
+
+    cleanup_plugin_test_env();
+}
+
+#[test]
+fn test_plugin_name_of() {
+    // Unix: the execute bit decides, and any extension is dropped from the name
+    assert_eq!(
+        plugin_name_of("timelog-jira", true, false),
+        Some("jira".to_string())
+    );
+    assert_eq!(
+        plugin_name_of("timelog-jira.sh", true, false),
+        Some("jira".to_string())
+    );
+    assert_eq!(plugin_name_of("timelog-jira", false, false), None);
+    assert_eq!(plugin_name_of("timelog-jira.json", true, false), None);
+    assert_eq!(plugin_name_of("timelog-jira.toml", true, false), None);
+    assert_eq!(plugin_name_of("jira", true, false), None);
+
+    // Windows: no execute bit, so the extension decides
+    for file in [
+        "timelog-jira.exe",
+        "timelog-jira.bat",
+        "timelog-jira.CMD",
+        "timelog-jira.ps1",
+    ] {
+        assert_eq!(
+            plugin_name_of(file, false, true),
+            Some("jira".to_string()),
+            "{file}"
+        );
+    }
+    assert_eq!(plugin_name_of("timelog-jira", true, true), None);
+    assert_eq!(plugin_name_of("timelog-jira.json", false, true), None);
+    assert_eq!(plugin_name_of("timelog-jira.txt", false, true), None);
+    assert_eq!(plugin_name_of("timelog-.exe", false, true), None);
+}
+
+#[test]
+#[serial]
+fn test_plugin_protocol_version() {
+    let _temp_dir = setup_plugin_test_env();
+
+    assert_eq!(
+        plugin_protocol_version(&serde_json::json!({})),
+        Ok(PLUGIN_PROTOCOL_VERSION)
+    );
+    assert_eq!(
+        plugin_protocol_version(&serde_json::json!({ "protocol_version": 1 })),
+        Ok(1)
+    );
+    assert!(
+        plugin_protocol_version(&serde_json::json!({ "protocol_version": "one" }))
+            .unwrap_err()
+            .contains("whole number")
+    );
+
+    // a plugin from the future is refused before it is ever started
+    let plugin_path = plugin_dir().unwrap().join("timelog-future");
+    let marker = plugin_dir().unwrap().join("ran");
+    fs::write(
+        &plugin_path,
+        format!("#!/bin/bash\ntouch {}\n", marker.display()),
+    )
+    .unwrap();
+    let mut perms = fs::metadata(&plugin_path).unwrap().permissions();
+    perms.set_mode(0o755);
+    fs::set_permissions(&plugin_path, perms).unwrap();
+
+    let input = PluginInput {
+        protocol_version: PLUGIN_PROTOCOL_VERSION,
+        records: vec![],
+        period: "today".to_string(),
+        config: serde_json::json!({ "protocol_version": PLUGIN_PROTOCOL_VERSION + 1 }),
+    };
+    let err = execute_plugin("future", &input, false, DEFAULT_PLUGIN_TIMEOUT).unwrap_err();
+    assert!(err.contains("Refusing to run plugin 'future'"), "{err}");
+    assert!(err.contains("upgrade timelog"), "{err}");
+    assert!(!marker.exists());
+
+    fs::write(
+        plugin_dir().unwrap().join("timelog-future.toml"),
+        format!("protocol_version = {}\n", PLUGIN_PROTOCOL_VERSION + 1),
+    )
+    .unwrap();
+    let problems = validate_config_files(&config_path().unwrap(), &plugin_dir().unwrap());
+    assert_eq!(problems.len(), 1);
+    assert_eq!(problems[0].key.as_deref(), Some("protocol_version"));
+
+    cleanup_plugin_test_env();
+}